@@ -147,6 +147,23 @@ pub type Communication<B> = rhododendron::Communication<B, <B as Block>::Hash, A
 /// Misbehavior observed from BFT participants.
 pub type Misbehavior<H> = rhododendron::Misbehavior<H, LocalizedSignature>;
 
+/// Outcome of evaluating a proposal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvaluationOutcome {
+	/// The proposal is valid.
+	Valid,
+	/// The proposal is invalid.
+	Invalid,
+	/// No vote should be cast on the proposal, for the given reason.
+	Abstain(String),
+}
+
+impl From<bool> for EvaluationOutcome {
+	fn from(valid: bool) -> Self {
+		if valid { EvaluationOutcome::Valid } else { EvaluationOutcome::Invalid }
+	}
+}
+
 /// Environment producer for a BFT instance. Creates proposer instance and communication streams.
 pub trait Environment<B: Block> {
 	/// The proposer type this creates.
@@ -175,12 +192,12 @@ pub trait Proposer<B: Block> {
 	/// Future that resolves to a committed proposal.
 	type Create: IntoFuture<Item=B,Error=Self::Error>;
 	/// Future that resolves when a proposal is evaluated.
-	type Evaluate: IntoFuture<Item=bool,Error=Self::Error>;
+	type Evaluate: IntoFuture<Item=EvaluationOutcome,Error=Self::Error>;
 
 	/// Create a proposal.
 	fn propose(&self) -> Self::Create;
 
-	/// Evaluate proposal. True means valid.
+	/// Evaluate proposal.
 	fn evaluate(&self, proposal: &B) -> Self::Evaluate;
 
 	/// Import witnessed misbehavior.
@@ -192,6 +209,13 @@ pub trait Proposer<B: Block> {
 
 	/// Hook called when a BFT round advances without a proposal.
 	fn on_round_end(&self, _round_number: usize, _proposed: bool) { }
+
+	/// Whether an error creating a proposal only means there is nothing worth proposing
+	/// this round. Rather than failing the agreement, the round then goes on without a
+	/// local proposal until it times out.
+	fn is_round_skipped(_error: &Self::Error) -> bool where Self: Sized {
+		false
+	}
 }
 
 /// Block import trait.
@@ -222,7 +246,7 @@ struct BftInstance<B: Block, P> {
 	key: Arc<ed25519::Pair>,
 	authorities: Vec<AuthorityId>,
 	parent_hash: B::Hash,
-	round_timeout_multiplier: u64,
+	base_round_timeout: Duration,
 	cache: Arc<Mutex<RoundCache<B::Hash>>>,
 	proposer: P,
 }
@@ -234,18 +258,16 @@ impl<B: Block, P: Proposer<B>> BftInstance<B, P>
 
 {
 	fn round_timeout_duration(&self, round: usize) -> Duration {
-		// 2^(min(6, x/8)) * 10
-		// Grows exponentially starting from 10 seconds, capped at 640 seconds.
+		// 2^(min(6, x/8)) * base
+		// Grows exponentially starting from the base timeout (10 seconds by default),
+		// capped at 64 times it.
 		const ROUND_INCREMENT_STEP: usize = 8;
 
 		let round = round / ROUND_INCREMENT_STEP;
 		let round = ::std::cmp::min(6, round) as u32;
 
-		let timeout = 1u64.checked_shl(round)
-			.unwrap_or_else(u64::max_value)
-			.saturating_mul(self.round_timeout_multiplier);
-
-		Duration::from_secs(timeout)
+		self.base_round_timeout.checked_mul(1 << round)
+			.unwrap_or_else(|| Duration::from_secs(u64::max_value()))
 	}
 
 	fn update_round_cache(&self, current_round: usize) {
@@ -267,15 +289,15 @@ impl<B: Block, P: Proposer<B>> rhododendron::Context for BftInstance<B, P>
 	type Signature = LocalizedSignature;
 	type Candidate = B;
 	type RoundTimeout = Box<Future<Item=(),Error=Self::Error>>;
-	type CreateProposal = <P::Create as IntoFuture>::Future;
-	type EvaluateProposal = <P::Evaluate as IntoFuture>::Future;
+	type CreateProposal = LocalProposal<<P::Create as IntoFuture>::Future, P>;
+	type EvaluateProposal = ProposalVote<<P::Evaluate as IntoFuture>::Future>;
 
 	fn local_id(&self) -> AuthorityId {
 		self.key.public().into()
 	}
 
 	fn proposal(&self) -> Self::CreateProposal {
-		self.proposer.propose().into_future()
+		LocalProposal::new(self.proposer.propose().into_future())
 	}
 
 	fn candidate_digest(&self, proposal: &B) -> B::Hash {
@@ -291,7 +313,10 @@ impl<B: Block, P: Proposer<B>> rhododendron::Context for BftInstance<B, P>
 	}
 
 	fn proposal_valid(&self, proposal: &B) -> Self::EvaluateProposal {
-		self.proposer.evaluate(proposal).into_future()
+		ProposalVote {
+			inner: self.proposer.evaluate(proposal).into_future(),
+			abstained: false,
+		}
 	}
 
 	fn begin_round_timeout(&self, round: usize) -> Self::RoundTimeout {
@@ -322,7 +347,7 @@ impl<B: Block, P: Proposer<B>> rhododendron::Context for BftInstance<B, P>
 			collect_pubkeys(accumulator.participants()));
 		debug!(target: "bft", "Voting authorities: {:?}",
 			collect_pubkeys(accumulator.voters()));
-		debug!(target: "bft", "Round {} should end in at most {} seconds from now", next_round, round_timeout.as_secs());
+		debug!(target: "bft", "Round {} should end in at most {:?} from now", next_round, round_timeout);
 
 		self.update_round_cache(next_round);
 
@@ -332,6 +357,76 @@ impl<B: Block, P: Proposer<B>> rhododendron::Context for BftInstance<B, P>
 	}
 }
 
+/// Vote on a proposal, derived from its evaluation outcome. Never resolves
+/// if the proposer abstains.
+// the local proposal, pending for the rest of the round if the proposer skips it.
+struct LocalProposal<F, P> {
+	inner: F,
+	skipped: bool,
+	_proposer: ::std::marker::PhantomData<P>,
+}
+
+impl<F, P> LocalProposal<F, P> {
+	fn new(inner: F) -> Self {
+		LocalProposal {
+			inner,
+			skipped: false,
+			_proposer: ::std::marker::PhantomData,
+		}
+	}
+}
+
+impl<F, P> Future for LocalProposal<F, P> where
+	F: Future,
+	F::Item: Block,
+	P: Proposer<F::Item, Error=F::Error>,
+{
+	type Item = F::Item;
+	type Error = F::Error;
+
+	fn poll(&mut self) -> Result<Async<F::Item>, F::Error> {
+		if self.skipped {
+			return Ok(Async::NotReady);
+		}
+
+		match self.inner.poll() {
+			Err(ref e) if P::is_round_skipped(e) => {
+				debug!(target: "bft", "Not proposing this round: {:?}", e);
+				self.skipped = true;
+				Ok(Async::NotReady)
+			}
+			result => result,
+		}
+	}
+}
+
+struct ProposalVote<F> {
+	inner: F,
+	abstained: bool,
+}
+
+impl<F: Future<Item=EvaluationOutcome>> Future for ProposalVote<F> {
+	type Item = bool;
+	type Error = F::Error;
+
+	fn poll(&mut self) -> Result<Async<bool>, F::Error> {
+		if self.abstained {
+			return Ok(Async::NotReady);
+		}
+
+		match self.inner.poll()? {
+			Async::Ready(EvaluationOutcome::Valid) => Ok(Async::Ready(true)),
+			Async::Ready(EvaluationOutcome::Invalid) => Ok(Async::Ready(false)),
+			Async::Ready(EvaluationOutcome::Abstain(reason)) => {
+				warn!(target: "bft", "Abstaining from vote on proposal: {}", reason);
+				self.abstained = true;
+				Ok(Async::NotReady)
+			}
+			Async::NotReady => Ok(Async::NotReady),
+		}
+	}
+}
+
 /// A future that resolves either when canceled (witnessing a block from the network at same height)
 /// or when agreement completes.
 pub struct BftFuture<B, P, I, InStream, OutSink> where
@@ -449,7 +544,7 @@ pub struct BftService<B: Block, P, I> {
 	client: Arc<I>,
 	live_agreement: Mutex<Option<(B::Header, AgreementHandle)>>,
 	round_cache: Arc<Mutex<RoundCache<B::Hash>>>,
-	round_timeout_multiplier: u64,
+	base_round_timeout: Duration,
 	key: Arc<ed25519::Pair>, // TODO: key changing over time.
 	factory: P,
 }
@@ -471,12 +566,30 @@ impl<B, P, I> BftService<B, P, I>
 				hash: None,
 				start_round: 0,
 			})),
-			round_timeout_multiplier: 10,
+			base_round_timeout: Duration::from_secs(10),
 			key: key, // TODO: key changing over time.
 			factory,
 		}
 	}
 
+	/// Set how long the first round of an agreement may take. Later rounds time out
+	/// after exponentially longer, up to 64 times as long.
+	pub fn with_base_round_timeout(mut self, timeout: Duration) -> Self {
+		self.base_round_timeout = timeout;
+		self
+	}
+
+	fn instance(&self, proposer: P::Proposer, parent_hash: B::Hash, authorities: Vec<AuthorityId>) -> BftInstance<B, P::Proposer> {
+		BftInstance {
+			proposer,
+			parent_hash,
+			cache: self.round_cache.clone(),
+			base_round_timeout: self.base_round_timeout,
+			key: self.key.clone(),
+			authorities,
+		}
+	}
+
 	/// Get the local Authority ID.
 	pub fn local_id(&self) -> AuthorityId {
 		// TODO: based on a header and some keystore.
@@ -526,14 +639,7 @@ impl<B, P, I> BftService<B, P, I>
 
 		let (proposer, input, output) = self.factory.init(header, &authorities, self.key.clone())?;
 
-		let bft_instance = BftInstance {
-			proposer,
-			parent_hash: hash.clone(),
-			cache: self.round_cache.clone(),
-			round_timeout_multiplier: self.round_timeout_multiplier,
-			key: self.key.clone(),
-			authorities: authorities,
-		};
+		let bft_instance = self.instance(proposer, hash.clone(), authorities);
 
 		let mut agreement = rhododendron::agree(
 			bft_instance,
@@ -836,7 +942,7 @@ mod tests {
 	impl Proposer<TestBlock> for DummyProposer {
 		type Error = Error;
 		type Create = Result<TestBlock, Error>;
-		type Evaluate = Result<bool, Error>;
+		type Evaluate = Result<EvaluationOutcome, Error>;
 
 		fn propose(&self) -> Result<TestBlock, Error> {
 
@@ -846,8 +952,8 @@ mod tests {
 			})
 		}
 
-		fn evaluate(&self, proposal: &TestBlock) -> Result<bool, Error> {
-			Ok(proposal.header.number == self.0)
+		fn evaluate(&self, proposal: &TestBlock) -> Result<EvaluationOutcome, Error> {
+			Ok((proposal.header.number == self.0).into())
 		}
 
 		fn import_misbehavior(&self, _misbehavior: Vec<(AuthorityId, Misbehavior<H256>)>) {}
@@ -855,6 +961,13 @@ mod tests {
 		fn round_proposer(&self, round_number: usize, authorities: &[AuthorityId]) -> AuthorityId {
 			authorities[round_number % authorities.len()].clone()
 		}
+
+		fn is_round_skipped(error: &Error) -> bool {
+			match *error.kind() {
+				ErrorKind::Msg(ref msg) => msg == "nothing to propose",
+				_ => false,
+			}
+		}
 	}
 
 	fn make_service(client: FakeClient)
@@ -867,7 +980,7 @@ mod tests {
 				hash: None,
 				start_round: 0,
 			})),
-			round_timeout_multiplier: 10,
+			base_round_timeout: Duration::from_secs(10),
 			key: Arc::new(Keyring::One.into()),
 			factory: DummyFactory
 		}
@@ -925,6 +1038,48 @@ mod tests {
 		assert!(rx.wait().is_ok());
 	}
 
+	#[test]
+	fn skipped_proposal_waits_for_round_timeout() {
+		let skipped: Result<TestBlock, Error> = Err("nothing to propose".into());
+		let mut proposal = LocalProposal::<_, DummyProposer>::new(skipped.into_future());
+		match proposal.poll() {
+			Ok(Async::NotReady) => {}
+			_ => panic!("skipped proposal should stay pending"),
+		}
+		assert!(proposal.poll().unwrap().is_not_ready());
+
+		let failed: Result<TestBlock, Error> = Err("proposer broke".into());
+		assert!(LocalProposal::<_, DummyProposer>::new(failed.into_future()).poll().is_err());
+	}
+
+	#[test]
+	fn base_round_timeout_is_applied_to_agreements() {
+		let client = FakeClient {
+			authorities: Vec::new(),
+			imported_heights: Mutex::new(HashSet::new()),
+		};
+		let service = make_service(client).with_base_round_timeout(Duration::from_secs(30));
+
+		let instance = service.instance(DummyProposer(1), Default::default(), Vec::new());
+
+		assert_eq!(instance.round_timeout_duration(0), Duration::from_secs(30));
+		assert_eq!(instance.round_timeout_duration(8), Duration::from_secs(60));
+	}
+
+	#[test]
+	fn sub_second_base_round_timeout_is_kept() {
+		let client = FakeClient {
+			authorities: Vec::new(),
+			imported_heights: Mutex::new(HashSet::new()),
+		};
+		let service = make_service(client).with_base_round_timeout(Duration::from_millis(1500));
+
+		let instance = service.instance(DummyProposer(1), Default::default(), Vec::new());
+
+		assert_eq!(instance.round_timeout_duration(0), Duration::from_millis(1500));
+		assert_eq!(instance.round_timeout_duration(100), Duration::from_millis(96_000));
+	}
+
 	#[test]
 	fn max_faulty() {
 		assert_eq!(max_faulty_of(3), 0);
@@ -23,6 +23,7 @@ use runtime_primitives::traits::{Header as HeaderT, Hash, Block as BlockT, One,
 use runtime_primitives::generic::BlockId;
 use {backend, error, Client, CallExecutor};
 use runtime_primitives::{ApplyResult, ApplyOutcome};
+use runtime_primitives::transaction_validity::TransactionValidity;
 use patricia_trie::NodeCodec;
 use primitives::{Blake2Hasher, RlpCodec};
 use hashdb::Hasher;
@@ -118,6 +119,27 @@ where
 		}
 	}
 
+	/// Check whether the extrinsic could be pushed onto the block, without modifying the builder.
+	///
+	/// This only runs the runtime's `validate_transaction` against the block's state so far, so
+	/// signature, fee payment and index are checked but the extrinsic is not dispatched. Returns
+	/// `Ok(false)` if the extrinsic is invalid, still depends on other transactions, or fails to
+	/// validate.
+	pub fn would_apply(&self, xt: &<Block as BlockT>::Extrinsic) -> error::Result<bool> {
+		let mut changes = self.changes.clone();
+		match self.executor.call_at_state(&self.state, &mut changes, "validate_transaction", &xt.encode(), native_when_possible()) {
+			Ok((result, _, _)) => match TransactionValidity::decode(&mut result.as_slice()) {
+				Some(TransactionValidity::Valid(_, ref requires, _, _)) => Ok(requires.is_empty()),
+				Some(TransactionValidity::Invalid) | Some(TransactionValidity::Unknown) => Ok(false),
+				None => Err(error::ErrorKind::CallResultDecode("validate_transaction").into()),
+			},
+			Err(e) => match *e.kind() {
+				error::ErrorKind::Execution(_) => Ok(false),
+				_ => Err(e),
+			},
+		}
+	}
+
 	/// Consume the builder to return a valid `Block` containing all pushed extrinsics.
 	pub fn bake(mut self) -> error::Result<Block> {
 		let (output, _, _) = self.executor.call_at_state(
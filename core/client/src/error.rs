@@ -94,6 +94,12 @@ error_chain! {
 			display("Remote data fetch has been failed"),
 		}
 
+		/// The runtime does not implement a requested API function.
+		ApiNotImplemented(method: &'static str) {
+			description("runtime API not implemented"),
+			display("Runtime does not implement {}", method),
+		}
+
 		/// Error decoding call result.
 		CallResultDecode(method: &'static str) {
 			description("Error decoding call result")
@@ -127,4 +133,11 @@ impl Error {
 	}
 }
 
-impl state_machine::Error for Error {}
+impl state_machine::Error for Error {
+	fn is_method_not_found(&self) -> bool {
+		match *self.kind() {
+			ErrorKind::Execution(ref e) => e.is_method_not_found(),
+			_ => false,
+		}
+	}
+}
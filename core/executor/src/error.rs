@@ -78,4 +78,11 @@ error_chain! {
 	}
 }
 
-impl state_machine::Error for Error {}
+impl state_machine::Error for Error {
+	fn is_method_not_found(&self) -> bool {
+		match *self.kind() {
+			ErrorKind::MethodNotFound(_) => true,
+			_ => false,
+		}
+	}
+}
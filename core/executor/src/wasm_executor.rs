@@ -520,6 +520,10 @@ impl WasmExecutor {
 
 		// finish instantiation by running 'start' function (if any).
 		let instance = intermediate_instance.run_start(&mut fec)?;
+		if instance.export_by_name(method).is_none() {
+			bail!(ErrorKind::MethodNotFound(method.to_owned()));
+		}
+
 		let size = data.len() as u32;
 		let offset = fec.heap.allocate(size);
 		memory.set(offset, &data)?;
@@ -587,6 +591,18 @@ mod tests {
 		assert!(output.is_err());
 	}
 
+	#[test]
+	fn missing_method_should_be_reported() {
+		let mut ext = TestExternalities::<_, RlpCodec>::default();
+		let test_code = include_bytes!("../wasm/target/wasm32-unknown-unknown/release/runtime_test.compact.wasm");
+
+		let output = WasmExecutor::new().call(&mut ext, 8, &test_code[..], "test_missing", &[]);
+		match output.unwrap_err().kind() {
+			&ErrorKind::MethodNotFound(ref method) => assert_eq!(method, "test_missing"),
+			e => panic!("Unexpected error: {:?}", e),
+		}
+	}
+
 	#[test]
 	fn storage_should_work() {
 		let mut ext = TestExternalities::<_, RlpCodec>::default();
@@ -69,7 +69,12 @@ pub use trie_backend::{TrieBackend, DBValue};
 /// State Machine Error bound.
 ///
 /// This should reflect WASM error type bound for future compatibility.
-pub trait Error: 'static + fmt::Debug + fmt::Display + Send {}
+pub trait Error: 'static + fmt::Debug + fmt::Display + Send {
+	/// Whether the error is caused by the runtime lacking the called method.
+	fn is_method_not_found(&self) -> bool {
+		false
+	}
+}
 
 impl Error for ExecutionError {}
 
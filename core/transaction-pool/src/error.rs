@@ -16,6 +16,7 @@
 
 //! External Error trait for extrinsic pool.
 
+use std::fmt;
 use txpool;
 
 /// Extrinsic pool error.
@@ -31,3 +32,28 @@ pub trait IntoPoolError: ::std::error::Error + Send + Sized {
 impl IntoPoolError for txpool::Error {
 	fn into_pool_error(self) -> Result<txpool::Error, Self> { Ok(self) }
 }
+
+/// Error refusing an extrinsic which was found to be invalid and is banned for a while.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BannedAsInvalid<Hash>(pub Hash);
+
+impl<Hash: fmt::Debug> fmt::Display for BannedAsInvalid<Hash> {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Extrinsic {:?} is temporarily banned after being found invalid", self.0)
+	}
+}
+
+impl<Hash: fmt::Debug> From<BannedAsInvalid<Hash>> for txpool::Error {
+	fn from(e: BannedAsInvalid<Hash>) -> Self {
+		e.to_string().into()
+	}
+}
+
+/// Error returned by submissions which must not evict other extrinsics.
+#[derive(Debug)]
+pub enum TrySubmitError<E> {
+	/// The pool is at capacity; submission may be retried later.
+	Busy,
+	/// The extrinsic was rejected.
+	Other(E),
+}
@@ -23,6 +23,7 @@
 
 extern crate futures;
 extern crate parking_lot;
+extern crate parity_codec as codec;
 extern crate sr_primitives as runtime_primitives;
 
 #[macro_use]
@@ -33,7 +34,6 @@ extern crate serde_derive;
 extern crate transaction_pool as txpool;
 #[cfg(test)] extern crate substrate_test_client as test_client;
 #[cfg(test)] extern crate substrate_keyring as keyring;
-#[cfg(test)] extern crate parity_codec as codec;
 
 pub mod watcher;
 mod error;
@@ -42,8 +42,11 @@ mod pool;
 mod rotator;
 
 pub use listener::Listener;
-pub use pool::{Pool, ChainApi, EventStream, Verified, VerifiedFor, ExtrinsicFor, ExHash, AllExtrinsics};
+pub use pool::{Pool, ChainApi, PartitionedStatus, EventStream, Verified, VerifiedFor, ExtrinsicFor, ExHash, AllExtrinsics, OrderedPending};
+#[cfg(feature = "ready-stream")]
+pub use pool::ReadyStream;
 pub use txpool::scoring;
 pub use txpool::{Error, ErrorKind};
-pub use error::IntoPoolError;
+pub use error::{BannedAsInvalid, IntoPoolError, TrySubmitError};
+pub use watcher::RemovalReason;
 pub use txpool::{Options, Status, LightStatus, VerifiedTransaction, Readiness, Transaction};
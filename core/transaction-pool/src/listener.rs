@@ -43,6 +43,11 @@ impl<H: ::std::hash::Hash + Eq + Copy + fmt::Debug + fmt::LowerHex + Default> Li
 		self.fire(hash, |watcher| watcher.broadcast(peers));
 	}
 
+	/// Notify the listeners about extrinsic removed as invalid.
+	pub fn removed(&mut self, hash: &H, reason: watcher::RemovalReason) {
+		self.fire(hash, |watcher| watcher.invalid(reason));
+	}
+
 	fn fire<F>(&mut self, hash: &H, fun: F) where F: FnOnce(&mut watcher::Sender<H>) {
 		let clean = if let Some(h) = self.watchers.get_mut(hash) {
 			fun(h);
@@ -15,37 +15,45 @@
 // along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::{
-	collections::{BTreeMap, HashMap},
+	cmp::Reverse,
+	collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque},
 	fmt,
 	sync::Arc,
 	time,
 };
+use codec::{Decode, Encode};
 use futures::sync::mpsc;
 use parking_lot::{Mutex, RwLock};
 use serde::{Serialize, de::DeserializeOwned};
 use txpool::{self, Scoring, Readiness};
 
-use error::IntoPoolError;
+use error::{BannedAsInvalid, IntoPoolError, TrySubmitError};
 use listener::Listener;
-use rotator::PoolRotator;
-use watcher::Watcher;
+use rotator::{Ban, PoolRotator};
+use watcher::{RemovalReason, Watcher};
 
 use runtime_primitives::{generic::BlockId, traits::Block as BlockT};
 
 /// Modification notification event stream type;
 pub type EventStream = mpsc::UnboundedReceiver<()>;
 
+/// Stream of extrinsics which became ready.
+#[cfg(feature = "ready-stream")]
+pub type ReadyStream<A> = mpsc::UnboundedReceiver<Arc<VerifiedFor<A>>>;
+
 /// Extrinsic hash type for a pool.
 pub type ExHash<A> = <A as ChainApi>::Hash;
 /// Extrinsic type for a pool.
 pub type ExtrinsicFor<A> = <<A as ChainApi>::Block as BlockT>::Extrinsic;
 /// Verified extrinsic data for `ChainApi`.
 pub type VerifiedFor<A> = Verified<ExtrinsicFor<A>, <A as ChainApi>::VEx>;
+/// Deterministically ordered pending extrinsics.
+pub type OrderedPending<A> = ::std::vec::IntoIter<Arc<VerifiedFor<A>>>;
 /// A collection of all extrinsics.
 pub type AllExtrinsics<A> = BTreeMap<<<A as ChainApi>::VEx as txpool::VerifiedTransaction>::Sender, Vec<ExtrinsicFor<A>>>;
 
 /// Verified extrinsic struct. Wraps original extrinsic and verification info.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Verified<Ex, VEx> {
 	/// Original extrinsic.
 	pub original: Ex,
@@ -53,6 +61,8 @@ pub struct Verified<Ex, VEx> {
 	pub verified: VEx,
 	/// Pool deadline, after it's reached we remove the extrinsic from the pool.
 	pub valid_till: time::Instant,
+	/// When the extrinsic was verified and imported.
+	pub imported_at: time::Instant,
 }
 
 impl<Ex, VEx> txpool::VerifiedTransaction for Verified<Ex, VEx>
@@ -91,7 +101,7 @@ pub trait ChainApi: Send + Sync {
 	/// Readiness evaluator
 	type Ready;
 	/// Error type.
-	type Error: From<txpool::Error> + IntoPoolError;
+	type Error: From<txpool::Error> + From<BannedAsInvalid<Self::Hash>> + IntoPoolError;
 	/// Score type.
 	type Score: ::std::cmp::Ord + Clone + Default + fmt::Debug + Send + Send + Sync + fmt::LowerHex;
 	/// Custom scoring update event type.
@@ -128,6 +138,31 @@ pub struct Ready<'a, 'b, B: 'a + ChainApi> {
 	context: B::Ready,
 	rotator: &'a PoolRotator<B::Hash>,
 	now: time::Instant,
+	#[cfg(feature = "ready-stream")]
+	ready_sinks: &'a Mutex<Vec<mpsc::UnboundedSender<Arc<VerifiedFor<B>>>>>,
+	#[cfg(feature = "ready-stream")]
+	announced_ready: &'a Mutex<HashSet<B::Hash>>,
+}
+
+impl<'a, 'b, B: ChainApi> Ready<'a, 'b, B> {
+	// announce an extrinsic to `ready_stream` subscribers the first time it's found ready,
+	// and forget it once it isn't, so it's announced again if it becomes ready again.
+	#[cfg(feature = "ready-stream")]
+	fn announce(&self, xt: &VerifiedFor<B>, is_ready: bool) {
+		let mut sinks = self.ready_sinks.lock();
+		if sinks.is_empty() {
+			return;
+		}
+
+		let hash = *txpool::VerifiedTransaction::hash(xt);
+		let mut announced = self.announced_ready.lock();
+		if !is_ready {
+			announced.remove(&hash);
+		} else if announced.insert(hash) {
+			let xt = Arc::new(xt.clone());
+			sinks.retain(|sink| sink.unbounded_send(xt.clone()).is_ok());
+		}
+	}
 }
 
 impl<'a, 'b, B: ChainApi> txpool::Ready<VerifiedFor<B>> for Ready<'a, 'b, B> {
@@ -137,7 +172,10 @@ impl<'a, 'b, B: ChainApi> txpool::Ready<VerifiedFor<B>> for Ready<'a, 'b, B> {
 			return Readiness::Stale;
 		}
 
-		self.api.is_ready(self.at, &mut self.context, xt)
+		let readiness = self.api.is_ready(self.at, &mut self.context, xt);
+		#[cfg(feature = "ready-stream")]
+		self.announce(xt, readiness == Readiness::Ready);
+		readiness
 	}
 }
 
@@ -175,7 +213,24 @@ impl<T: ChainApi> Scoring<VerifiedFor<T>> for ScoringAdapter<T> {
 /// Transactions that don't get included within the limit are removed from the pool.
 const POOL_TIME: time::Duration = time::Duration::from_secs(60 * 5);
 
+/// Time extrinsics removed as invalid are kept around to be requeued.
+const QUARANTINE_TIME: time::Duration = time::Duration::from_secs(60);
+
+/// Numbers of extrinsics in the pool by readiness, along with their size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PartitionedStatus {
+	/// Extrinsics which could be included in the next block.
+	pub ready: usize,
+	/// Extrinsics waiting for others to be included first.
+	pub future: usize,
+	/// Total encoded size of all extrinsics in the pool, stale ones included.
+	pub total_bytes: usize,
+}
+
 /// Extrinsics pool.
+///
+/// The locks of the pool don't poison, so a panic in a `ChainApi` callback made while
+/// one is held doesn't leave the pool unusable.
 pub struct Pool<B: ChainApi> {
 	api: B,
 	pool: RwLock<txpool::Pool<
@@ -184,20 +239,38 @@ pub struct Pool<B: ChainApi> {
 		Listener<B::Hash>,
 	>>,
 	import_notification_sinks: Mutex<Vec<mpsc::UnboundedSender<()>>>,
+	#[cfg(feature = "ready-stream")]
+	ready_sinks: Mutex<Vec<mpsc::UnboundedSender<Arc<VerifiedFor<B>>>>>,
+	#[cfg(feature = "ready-stream")]
+	announced_ready: Mutex<HashSet<B::Hash>>,
 	rotator: PoolRotator<B::Hash>,
+	options: RwLock<txpool::Options>,
+	// extrinsics recently removed as invalid, when they are forgotten and why they were removed.
+	quarantine: Mutex<Vec<(time::Instant, B::Hash, ExtrinsicFor<B>, RemovalReason)>>,
 }
 
 impl<B: ChainApi> Pool<B> {
 	/// Create a new transaction pool.
 	pub fn new(options: txpool::Options, api: B) -> Self {
 		Pool {
-			pool: RwLock::new(txpool::Pool::new(Listener::default(), ScoringAdapter::<B>(Default::default()), options)),
+			pool: RwLock::new(txpool::Pool::new(Listener::default(), ScoringAdapter::<B>(Default::default()), options.clone())),
 			import_notification_sinks: Default::default(),
+			#[cfg(feature = "ready-stream")]
+			ready_sinks: Default::default(),
+			#[cfg(feature = "ready-stream")]
+			announced_ready: Default::default(),
 			api,
 			rotator: Default::default(),
+			options: RwLock::new(options),
+			quarantine: Default::default(),
 		}
 	}
 
+	/// The chain API the pool checks extrinsics with.
+	pub fn api(&self) -> &B {
+		&self.api
+	}
+
 	/// Imports a pre-verified extrinsic to the pool.
 	pub fn import(&self, xt: VerifiedFor<B>) -> Result<Arc<VerifiedFor<B>>, B::Error> {
 		let result = self.pool.write().import(xt)?;
@@ -215,6 +288,17 @@ impl<B: ChainApi> Pool<B> {
 		stream
 	}
 
+	/// Return a stream of extrinsics as they become ready.
+	///
+	/// Extrinsics are announced as the readiness checks the pool does anyway find them
+	/// ready, i.e. when the pool is culled or its pending set is retrieved, not on import.
+	#[cfg(feature = "ready-stream")]
+	pub fn ready_stream(&self) -> ReadyStream<B> {
+		let (sink, stream) = mpsc::unbounded();
+		self.ready_sinks.lock().push(sink);
+		stream
+	}
+
 	/// Invoked when extrinsics are broadcasted.
 	pub fn on_broadcasted(&self, propagated: HashMap<B::Hash, Vec<String>>) {
 		for (hash, peers) in propagated.into_iter() {
@@ -226,25 +310,27 @@ impl<B: ChainApi> Pool<B> {
 	pub fn submit_at<T>(&self, at: &BlockId<B::Block>, xts: T) -> Result<Vec<Arc<VerifiedFor<B>>>, B::Error> where
 		T: IntoIterator<Item=ExtrinsicFor<B>>
 	{
-		xts
+		let imported = xts
 			.into_iter()
 			.map(|xt| {
 				match self.api.verify_transaction(at, &xt) {
-					Ok(ref verified) if self.rotator.is_banned(txpool::VerifiedTransaction::hash(verified)) => {
-						return (Err(txpool::Error::from("Temporarily Banned".to_owned()).into()), xt)
-					},
+					Ok(verified) => (self.check_ban(txpool::VerifiedTransaction::hash(&verified)).map(|()| verified), xt),
 					result => (result, xt),
 				}
 			})
 			.map(|(v, xt)| {
+				let now = time::Instant::now();
 				let xt = Verified {
 					original: xt,
 					verified: v?,
-					valid_till: time::Instant::now() + POOL_TIME,
+					valid_till: now + POOL_TIME,
+					imported_at: now,
 				};
 				Ok(self.pool.write().import(xt)?)
 			})
-			.collect()
+			.collect();
+
+		imported
 	}
 
 	/// Imports one unverified extrinsic to the pool
@@ -252,6 +338,87 @@ impl<B: ChainApi> Pool<B> {
 		Ok(self.submit_at(at, ::std::iter::once(xt))?.pop().expect("One extrinsic passed; one result returned; qed"))
 	}
 
+	/// Imports one unverified extrinsic to the pool unless the pool is at capacity.
+	///
+	/// Unlike `submit_one` this never evicts other extrinsics to make room for the new one.
+	pub fn try_submit(&self, at: &BlockId<B::Block>, xt: ExtrinsicFor<B>) -> Result<Arc<VerifiedFor<B>>, TrySubmitError<B::Error>> {
+		if self.is_full() {
+			return Err(TrySubmitError::Busy);
+		}
+
+		self.submit_one(at, xt).map_err(TrySubmitError::Other)
+	}
+
+	/// Returns `true` if the pool has reached its count or memory limit.
+	pub fn is_full(&self) -> bool {
+		let status = self.light_status();
+		let options = self.options.read();
+		status.transaction_count >= options.max_count || status.mem_usage >= options.max_mem_usage
+	}
+
+	/// Change the limits of the pool, evicting extrinsics which exceed the new limits.
+	///
+	/// Returns the number of evicted extrinsics.
+	pub fn set_options(&self, options: txpool::Options) -> usize where
+		VerifiedFor<B>: Clone,
+	{
+		let mut pool = self.pool.write();
+		let all: Vec<_> = pool.unordered_pending(AlwaysReady).collect();
+
+		// the limits are fixed at construction, so re-import everything into a new pool.
+		let listener = ::std::mem::replace(pool.listener_mut(), Listener::default());
+		let mut resized = txpool::Pool::new(listener, ScoringAdapter::<B>(Default::default()), options.clone());
+		for xt in &all {
+			if let Err(e) = resized.import((**xt).clone()) {
+				debug!(target: "transaction-pool", "Evicting extrinsic after pool resize: {}", e);
+				txpool::Listener::dropped(resized.listener_mut(), xt, None);
+			}
+		}
+
+		let evicted = all.len() - resized.light_status().transaction_count;
+		*pool = resized;
+		*self.options.write() = options;
+		evicted
+	}
+
+	/// Replace the contents of the pool with given unverified extrinsics.
+	///
+	/// The pool is never observed holding a mix of the old and the new extrinsics.
+	/// Returns the outcome of importing each of the new ones.
+	pub fn replace_all(&self, at: &BlockId<B::Block>, xts: Vec<ExtrinsicFor<B>>) -> Vec<Result<Arc<VerifiedFor<B>>, B::Error>> {
+		let now = time::Instant::now();
+		let verified: Vec<Result<_, B::Error>> = xts.into_iter()
+			.map(|xt| {
+				let verified = self.api.verify_transaction(at, &xt)?;
+				self.check_ban(txpool::VerifiedTransaction::hash(&verified))?;
+				Ok(Verified {
+					original: xt,
+					verified,
+					valid_till: now + POOL_TIME,
+					imported_at: now,
+				})
+			})
+			.collect();
+
+		let imported = {
+			let mut pool = self.pool.write();
+			let listener = ::std::mem::replace(pool.listener_mut(), Listener::default());
+			let options = self.options.read().clone();
+			let old = ::std::mem::replace(&mut *pool, txpool::Pool::new(listener, ScoringAdapter::<B>(Default::default()), options));
+			for xt in old.unordered_pending(AlwaysReady) {
+				txpool::Listener::dropped(pool.listener_mut(), &xt, None);
+			}
+
+			verified.into_iter()
+				.map(|xt| Ok(pool.import(xt?)?))
+				.collect()
+		};
+
+		#[cfg(feature = "ready-stream")]
+		self.announced_ready.lock().clear();
+		imported
+	}
+
 	/// Import a single extrinsic and starts to watch their progress in the pool.
 	pub fn submit_and_watch(&self, at: &BlockId<B::Block>, xt: ExtrinsicFor<B>) -> Result<Watcher<B::Hash>, B::Error> {
 		let xt = self.submit_at(at, Some(xt))?.pop().expect("One extrinsic passed; one result returned; qed");
@@ -260,17 +427,54 @@ impl<B: ChainApi> Pool<B> {
 
 	/// Remove from the pool.
 	pub fn remove(&self, hashes: &[B::Hash], is_valid: bool) -> Vec<Option<Arc<VerifiedFor<B>>>> {
+		let reason = if is_valid { None } else { Some(RemovalReason::Invalid) };
+		self.remove_as(hashes, reason)
+	}
+
+	/// Remove invalid extrinsics from the pool, recording why.
+	///
+	/// The reason is reported to the watchers of the removed extrinsics and is kept
+	/// for as long as they are quarantined.
+	pub fn remove_with_reason(&self, hashes: &[B::Hash], reason: RemovalReason) -> Vec<Option<Arc<VerifiedFor<B>>>> {
+		let removed = self.remove_as(hashes, Some(reason));
+
+		let mut pool = self.pool.write();
+		for (hash, _) in hashes.iter().zip(&removed).filter(|&(_, xt)| xt.is_some()) {
+			pool.listener_mut().removed(hash, reason);
+		}
+
+		removed
+	}
+
+	/// Why the extrinsic with given hash was recently removed as invalid, if it was.
+	pub fn removal_reason(&self, hash: &B::Hash) -> Option<RemovalReason> {
+		let now = time::Instant::now();
+		self.quarantine.lock().iter()
+			.find(|&&(until, ref quarantined, _, _)| until > now && quarantined == hash)
+			.map(|&(_, _, _, reason)| reason)
+	}
+
+	// removes extrinsics, quarantining them if they are removed for a reason.
+	fn remove_as(&self, hashes: &[B::Hash], reason: Option<RemovalReason>) -> Vec<Option<Arc<VerifiedFor<B>>>> {
+		let is_valid = reason.is_none();
 		let mut pool = self.pool.write();
 		let mut results = Vec::with_capacity(hashes.len());
 
 		// temporarily ban invalid transactions
+		let now = time::Instant::now();
 		if !is_valid {
 			debug!(target: "transaction-pool", "Banning invalid transactions: {:?}", hashes);
-			self.rotator.ban(&time::Instant::now(), hashes);
+			self.rotator.ban_invalid(&now, hashes);
 		}
 
+		let mut quarantine = self.quarantine.lock();
+		quarantine.retain(|&(until, _, _, _)| until > now);
 		for hash in hashes {
-			results.push(pool.remove(hash, is_valid));
+			let removed = pool.remove(hash, is_valid);
+			if let (Some(reason), Some(xt)) = (reason, removed.as_ref()) {
+				quarantine.push((now + QUARANTINE_TIME, *hash, xt.original.clone(), reason));
+			}
+			results.push(removed);
 		}
 
 		results
@@ -285,7 +489,22 @@ impl<B: ChainApi> Pool<B> {
 	{
 		self.rotator.clear_timeouts(&time::Instant::now());
 		let ready = self.ready(at);
-		self.pool.write().cull(senders, ready)
+		let mut pool = self.pool.write();
+		let removed = pool.cull(senders, ready);
+
+		// forget announced extrinsics which left the pool in other ways than being culled.
+		#[cfg(feature = "ready-stream")]
+		{
+			use txpool::VerifiedTransaction;
+
+			let mut announced = self.announced_ready.lock();
+			if !announced.is_empty() {
+				let held: HashSet<_> = pool.unordered_pending(AlwaysReady).map(|xt| *xt.hash()).collect();
+				announced.retain(|hash| held.contains(hash));
+			}
+		}
+
+		removed
 	}
 
 	/// Cull old transactions from the queue.
@@ -293,12 +512,80 @@ impl<B: ChainApi> Pool<B> {
 		Ok(self.cull_from(at, None))
 	}
 
+	/// Re-verify all extrinsics in the pool at given block and cull the ones which are no longer valid.
+	///
+	/// Should be called after a chain re-organisation, since the validity and readiness of
+	/// extrinsics may differ on the new best chain. Returns the number of removed extrinsics.
+	pub fn revalidate(&self, at: &BlockId<B::Block>) -> usize {
+		use txpool::VerifiedTransaction;
+
+		let all: Vec<_> = self.pool.read().unordered_pending(AlwaysReady).collect();
+		let invalid: Vec<_> = all.iter()
+			.filter(|xt| self.api.verify_transaction(at, &xt.original).is_err())
+			.map(|xt| *xt.hash())
+			.collect();
+
+		if !invalid.is_empty() {
+			debug!(target: "transaction-pool", "Removing extrinsics invalid after re-verification: {:?}", invalid);
+		}
+
+		let removed = self.remove(&invalid, false).into_iter().filter(Option::is_some).count();
+		removed + self.cull_from(at, None)
+	}
+
 	/// Cull transactions from the queue and then compute the pending set.
+	///
+	/// The pending set is ordered by score (highest first) and then by sender, extrinsics
+	/// from the same sender being kept in their relative order. This makes the order
+	/// independent of when the extrinsics were imported.
 	pub fn cull_and_get_pending<F, T>(&self, at: &BlockId<B::Block>, f: F) -> Result<T, B::Error> where
-		F: FnOnce(txpool::PendingIterator<VerifiedFor<B>, Ready<B>, ScoringAdapter<B>, Listener<B::Hash>>) -> T,
+		F: FnOnce(OrderedPending<B>) -> T,
 	{
 		self.cull_from(at, None);
-		Ok(self.pending(at, f))
+		Ok(f(self.ordered_pending(at).into_iter()))
+	}
+
+	fn ordered_pending(&self, at: &BlockId<B::Block>) -> Vec<Arc<VerifiedFor<B>>> {
+		use txpool::VerifiedTransaction;
+
+		let pending: Vec<_> = self.pending(at, |p| p.collect());
+		let count = pending.len();
+
+		// the pending iterator keeps extrinsics from a single sender in order.
+		let mut by_sender = BTreeMap::new();
+		for xt in pending {
+			by_sender.entry(xt.sender().clone()).or_insert_with(VecDeque::new).push_back(xt);
+		}
+
+		let mut queues = Vec::with_capacity(by_sender.len());
+		let mut heap = BinaryHeap::new();
+		for (sender, xts) in by_sender {
+			let xts: Vec<_> = xts.into_iter()
+				.enumerate()
+				.map(|(i, transaction)| txpool::Transaction { insertion_id: i as u64, transaction })
+				.collect();
+			let mut scores = vec![B::Score::default(); xts.len()];
+			for i in 0..xts.len() {
+				B::update_scores(&xts, &mut scores, txpool::scoring::Change::InsertedAt(i));
+			}
+
+			let mut queue: VecDeque<_> = xts.into_iter().map(|xt| xt.transaction).zip(scores).collect();
+			if let Some(score) = queue.front().map(|head| head.1.clone()) {
+				heap.push((score, Reverse(sender), queues.len()));
+			}
+			queues.push(queue);
+		}
+
+		let mut ordered = Vec::with_capacity(count);
+		while let Some((_, sender, idx)) = heap.pop() {
+			let (xt, _) = queues[idx].pop_front().expect("only non-empty queues are on the heap; qed");
+			ordered.push(xt);
+			if let Some(score) = queues[idx].front().map(|head| head.1.clone()) {
+				heap.push((score, sender, idx));
+			}
+		}
+
+		ordered
 	}
 
 	/// Get the full status of the queue (including readiness)
@@ -311,6 +598,53 @@ impl<B: ChainApi> Pool<B> {
 		self.pool.read().light_status()
 	}
 
+	/// Count the extrinsics which are ready and those which are future at given block.
+	pub fn partitioned_status(&self, at: &BlockId<B::Block>) -> PartitionedStatus {
+		use txpool::{Ready as ReadyT, VerifiedTransaction};
+
+		let pool = self.pool.read();
+		let senders: HashSet<_> = pool.unordered_pending(AlwaysReady).map(|xt| xt.sender().clone()).collect();
+
+		// readiness depends on the extrinsics preceding it from the same sender.
+		let mut ready = self.ready(at);
+		let mut status = PartitionedStatus::default();
+		for sender in senders {
+			for xt in pool.pending_from_sender(AlwaysReady, &sender) {
+				status.total_bytes += xt.original.encode().len();
+				match ready.is_ready(&xt) {
+					Readiness::Ready => status.ready += 1,
+					Readiness::Future => status.future += 1,
+					Readiness::Stale => {}
+				}
+			}
+		}
+		status
+	}
+
+	/// Check readiness of the extrinsic with given hash at given block.
+	///
+	/// Returns `None` if the extrinsic is not in the pool.
+	pub fn status_of(&self, at: &BlockId<B::Block>, hash: &B::Hash) -> Option<Readiness> {
+		use txpool::{Ready as ReadyT, VerifiedTransaction};
+
+		let pool = self.pool.read();
+		let sender = pool.unordered_pending(AlwaysReady)
+			.find(|xt| xt.hash() == hash)?
+			.sender()
+			.clone();
+
+		// readiness depends on the extrinsics preceding it from the same sender.
+		let mut ready = self.ready(at);
+		for xt in pool.pending_from_sender(AlwaysReady, &sender) {
+			let readiness = ready.is_ready(&xt);
+			if xt.hash() == hash {
+				return Some(readiness);
+			}
+		}
+
+		None
+	}
+
 	/// Removes all transactions from given sender
 	pub fn remove_sender(&self, sender: <B::VEx as txpool::VerifiedTransaction>::Sender) -> Vec<Arc<VerifiedFor<B>>> {
 		let mut pool = self.pool.write();
@@ -320,6 +654,24 @@ impl<B: ChainApi> Pool<B> {
 		pending
 	}
 
+	/// Removes all extrinsics from given sender, ready or not, returning their hashes.
+	pub fn purge_sender(&self, sender: &<B::VEx as txpool::VerifiedTransaction>::Sender) -> Vec<B::Hash> {
+		use txpool::VerifiedTransaction;
+
+		let removed = self.remove_sender(sender.clone());
+		debug!(target: "transaction-pool", "Purged {} extrinsics from {:?}", removed.len(), sender);
+		removed.iter().map(|xt| *xt.hash()).collect()
+	}
+
+	/// Get the lowest-index ready extrinsic of each sender at given block.
+	pub fn next_ready_per_sender(&self, at: &BlockId<B::Block>) -> Vec<Arc<VerifiedFor<B>>> {
+		use txpool::VerifiedTransaction;
+
+		// extrinsics of a sender are pending in order, so the first seen is the next one.
+		let mut senders = HashSet::new();
+		self.pending(at, |pending| pending.filter(|xt| senders.insert(xt.sender().clone())).collect())
+	}
+
 	/// Retrieve the pending set. Be careful to not leak the pool `ReadGuard` to prevent deadlocks.
 	pub fn pending<F, T>(&self, at: &BlockId<B::Block>, f: F) -> T where
 		F: FnOnce(txpool::PendingIterator<VerifiedFor<B>, Ready<B>, ScoringAdapter<B>, Listener<B::Hash>>) -> T,
@@ -352,6 +704,31 @@ impl<B: ChainApi> Pool<B> {
 		}
 	}
 
+	/// Re-verify extrinsics recently removed as invalid at given block and re-admit those
+	/// which are valid now, e.g. ones which only failed because of their ordering.
+	///
+	/// Extrinsics are only quarantined for a short while. Returns the number re-admitted.
+	pub fn requeue_quarantined(&self, at: &BlockId<B::Block>) -> usize {
+		let now = time::Instant::now();
+		let quarantined: Vec<_> = self.quarantine.lock().drain(..)
+			.filter(|&(until, _, _, _)| until > now)
+			.collect();
+
+		let mut requeued = 0;
+		for (until, hash, xt, reason) in quarantined {
+			self.rotator.unban(&[hash]);
+			match self.submit_one(at, xt.clone()) {
+				Ok(_) => requeued += 1,
+				Err(e) => {
+					debug!(target: "transaction-pool", "Keeping {:?} quarantined: {}", hash, e);
+					self.rotator.ban_invalid(&now, &[hash]);
+					self.quarantine.lock().push((until, hash, xt, reason));
+				}
+			}
+		}
+		requeued
+	}
+
 	/// Retrieve all transactions in the pool grouped by sender.
 	pub fn all(&self) -> AllExtrinsics<B> {
 		use txpool::VerifiedTransaction;
@@ -367,6 +744,52 @@ impl<B: ChainApi> Pool<B> {
 		})
 	}
 
+	/// Get how long each extrinsic has been in the pool.
+	pub fn ages(&self) -> Vec<(B::Hash, time::Duration)> {
+		use txpool::VerifiedTransaction;
+
+		let now = time::Instant::now();
+		self.pool.read().unordered_pending(AlwaysReady)
+			.map(|xt| (*xt.hash(), now.duration_since(xt.imported_at)))
+			.collect()
+	}
+
+	/// Encode all extrinsics held by the pool, so they can be restored e.g. after a restart.
+	pub fn snapshot(&self) -> Vec<Vec<u8>> {
+		self.pool.read().unordered_pending(AlwaysReady).map(|xt| xt.original.encode()).collect()
+	}
+
+	/// Re-verify and import extrinsics previously taken with `snapshot`.
+	///
+	/// Extrinsics which fail to decode or verify are dropped. Returns the number restored.
+	pub fn restore(&self, snapshot: Vec<Vec<u8>>, at: &BlockId<B::Block>) -> usize {
+		let mut restored = 0;
+		for encoded in snapshot {
+			let xt = match ExtrinsicFor::<B>::decode(&mut &encoded[..]) {
+				Some(xt) => xt,
+				None => {
+					debug!(target: "transaction-pool", "Dropping undecodable extrinsic from snapshot");
+					continue;
+				}
+			};
+
+			match self.submit_one(at, xt) {
+				Ok(_) => restored += 1,
+				Err(e) => debug!(target: "transaction-pool", "Dropping extrinsic from snapshot: {}", e),
+			}
+		}
+		restored
+	}
+
+	// refuse banned extrinsics, telling those found to be invalid apart.
+	fn check_ban(&self, hash: &B::Hash) -> Result<(), B::Error> {
+		match self.rotator.ban_of(hash) {
+			Some(Ban::Invalid) => Err(BannedAsInvalid(*hash).into()),
+			Some(Ban::Stale) => Err(txpool::Error::from("Temporarily Banned".to_owned()).into()),
+			None => Ok(()),
+		}
+	}
+
 	fn ready<'a, 'b>(&'a self, at: &'b BlockId<B::Block>) -> Ready<'a, 'b, B> {
 		Ready {
 			api: &self.api,
@@ -374,6 +797,10 @@ impl<B: ChainApi> Pool<B> {
 			context: self.api.ready(),
 			at,
 			now: time::Instant::now(),
+			#[cfg(feature = "ready-stream")]
+			ready_sinks: &self.ready_sinks,
+			#[cfg(feature = "ready-stream")]
+			announced_ready: &self.announced_ready,
 		}
 	}
 }
@@ -389,10 +816,11 @@ impl<VEx> txpool::Ready<VEx> for AlwaysReady {
 #[cfg(test)]
 pub mod tests {
 	use txpool;
+	use futures::{Future, Stream};
 	use super::{VerifiedFor, ExtrinsicFor};
 	use std::collections::HashMap;
 	use std::cmp::Ordering;
-	use {Pool, ChainApi, scoring, Readiness};
+	use {Pool, ChainApi, PartitionedStatus, RemovalReason, scoring, Readiness, TrySubmitError, BannedAsInvalid};
 	use keyring::Keyring::{self, *};
 	use codec::Encode;
 	use test_client::runtime::{AccountId, Block, Hash, Index, Extrinsic, Transfer};
@@ -427,6 +855,9 @@ pub mod tests {
 
 	struct TestApi;
 
+	// readiness checks of extrinsics with this nonce panic.
+	const PANICKING_NONCE: Index = 0xdead_beef;
+
 	impl TestApi {
 		fn default() -> Self {
 			TestApi
@@ -454,6 +885,7 @@ pub mod tests {
 		}
 
 		fn is_ready(&self, at: &BlockId, nonce_cache: &mut Self::Ready, xt: &VerifiedFor<Self>) -> Readiness {
+			assert!(xt.original.transfer.nonce != PANICKING_NONCE, "readiness check panicked");
 			let sender = xt.verified.sender;
 			let next_index = nonce_cache.entry(sender)
 				.or_insert_with(|| index(at, sender));
@@ -601,6 +1033,295 @@ pub mod tests {
 		assert_eq!(pending, vec![]);
 
 		// then
-		pool.submit_one(&BlockId::number(0), uxt.clone()).unwrap_err();
+		match *pool.submit_one(&BlockId::number(0), uxt.clone()).unwrap_err().kind() {
+			txpool::ErrorKind::Msg(ref msg) => assert_eq!(*msg, BannedAsInvalid(hash).to_string()),
+			ref e => panic!("Unexpected error: {:?}", e),
+		}
+	}
+
+	#[test]
+	fn status_of_should_follow_nonce_gaps() {
+		let pool = pool();
+		let gapped = *pool.submit_one(&BlockId::number(0), uxt(Alice, 210)).unwrap().hash();
+		assert_eq!(pool.status_of(&BlockId::number(0), &gapped), Some(Readiness::Future));
+
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 209)).unwrap();
+		assert_eq!(pool.status_of(&BlockId::number(0), &gapped), Some(Readiness::Ready));
+		assert_eq!(pool.status_of(&BlockId::number(0), &Default::default()), None);
+	}
+
+	#[cfg(feature = "ready-stream")]
+	#[test]
+	fn ready_stream_should_announce_ready_extrinsics() {
+		let pool = pool();
+		let stream = pool.ready_stream();
+
+		let future = *pool.submit_one(&BlockId::number(0), uxt(Alice, 210)).unwrap().hash();
+		let ready = *pool.submit_one(&BlockId::number(0), uxt(Alice, 209)).unwrap().hash();
+		pool.cull(&BlockId::number(0)).unwrap();
+		// already announced extrinsics aren't announced again.
+		pool.cull(&BlockId::number(0)).unwrap();
+		drop(pool);
+
+		let announced: Vec<_> = stream.map(|xt| *xt.hash()).collect().wait().unwrap();
+		assert_eq!(announced, vec![ready, future]);
+	}
+
+	#[test]
+	fn purge_sender_should_only_remove_that_sender() {
+		let pool = pool();
+		let bob_index = index(&BlockId::number(0), Bob.to_raw_public().into());
+		let mut alice: Vec<_> = [209, 210, 212].iter()
+			.map(|&nonce| *pool.submit_one(&BlockId::number(0), uxt(Alice, nonce)).unwrap().hash())
+			.collect();
+		pool.submit_one(&BlockId::number(0), uxt(Bob, bob_index)).unwrap();
+
+		let mut purged = pool.purge_sender(&Alice.to_raw_public().into());
+		purged.sort();
+		alice.sort();
+		assert_eq!(purged, alice);
+
+		let remaining: Vec<AccountId> = pool.all().keys().cloned().collect();
+		assert_eq!(remaining, vec![Bob.to_raw_public().into()]);
+	}
+
+	#[test]
+	fn try_submit_should_not_evict_when_full() {
+		let pool = Pool::new(txpool::Options {
+			max_count: 2,
+			..Default::default()
+		}, TestApi::default());
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 209)).unwrap();
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 210)).unwrap();
+		assert!(pool.is_full());
+
+		// when
+		let result = pool.try_submit(&BlockId::number(0), uxt(Alice, 211));
+
+		// then
+		match result {
+			Err(TrySubmitError::Busy) => {},
+			other => panic!("Expected busy error, got {:?}", other),
+		}
+		let pending: Vec<_> = pool.cull_and_get_pending(&BlockId::number(0), |p| p.map(|a| (*a.sender(), a.original.transfer.nonce)).collect()).unwrap();
+		assert_eq!(pending, vec![(Alice.to_raw_public().into(), 209), (Alice.to_raw_public().into(), 210)]);
+	}
+
+	#[test]
+	fn revalidate_should_update_ready_and_future() {
+		let pool = pool();
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 209)).unwrap();
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 211)).unwrap();
+		let pending: Vec<_> = pool.pending(&BlockId::number(0), |p| p.map(|a| a.original.transfer.nonce).collect());
+		assert_eq!(pending, vec![209]);
+
+		// when
+		// the account nonce is now 211 on the new best block.
+		assert_eq!(pool.revalidate(&BlockId::number(2)), 1);
+
+		// then
+		let pending: Vec<_> = pool.pending(&BlockId::number(2), |p| p.map(|a| a.original.transfer.nonce).collect());
+		assert_eq!(pending, vec![211]);
+		assert_eq!(pool.light_status().transaction_count, 1);
+	}
+
+	#[test]
+	fn pending_order_should_be_deterministic() {
+		fn uxt_with(who: Keyring, nonce: Index, amount: u64) -> Extrinsic {
+			let transfer = Transfer {
+				from: who.to_raw_public().into(),
+				to: AccountId::default(),
+				nonce,
+				amount,
+			};
+			let signature = transfer.using_encoded(|e| who.sign(e));
+			Extrinsic {
+				transfer,
+				signature: signature.into(),
+			}
+		}
+
+		let alice = Alice.to_raw_public()[0] as u64;
+		let bob = Bob.to_raw_public()[0] as u64;
+		let xts = vec![
+			uxt_with(Bob, bob + 1, 1),
+			uxt_with(Alice, alice, 1),
+			uxt_with(Alice, alice + 1, 1),
+			uxt_with(Bob, bob, 3),
+		];
+
+		let order = |xts: Vec<Extrinsic>| {
+			let pool = pool();
+			pool.submit_at(&BlockId::number(0), xts).unwrap();
+			let first: Vec<_> = pool.cull_and_get_pending(&BlockId::number(0), |p| p.map(|a| (*a.sender(), a.original.transfer.nonce)).collect()).unwrap();
+			let second: Vec<_> = pool.cull_and_get_pending(&BlockId::number(0), |p| p.map(|a| (*a.sender(), a.original.transfer.nonce)).collect()).unwrap();
+			assert_eq!(first, second);
+			first
+		};
+
+		// then
+		// Bob's first extrinsic has the best score, the rest are tied and ordered by sender.
+		let (a, b): (AccountId, AccountId) = (Alice.to_raw_public().into(), Bob.to_raw_public().into());
+		let expected = if a < b {
+			vec![(b, bob), (a, alice), (a, alice + 1), (b, bob + 1)]
+		} else {
+			vec![(b, bob), (b, bob + 1), (a, alice), (a, alice + 1)]
+		};
+		assert_eq!(order(xts.clone()), expected);
+		assert_eq!(order(xts.into_iter().rev().collect()), expected);
+	}
+
+	#[test]
+	fn set_options_should_evict_excess_extrinsics() {
+		let pool = pool();
+		for nonce in 209..214 {
+			pool.submit_one(&BlockId::number(0), uxt(Alice, nonce)).unwrap();
+		}
+
+		let evicted = pool.set_options(txpool::Options { max_count: 2, ..Default::default() });
+
+		assert_eq!(evicted, 3);
+		assert_eq!(pool.light_status().transaction_count, 2);
+		assert!(pool.is_full());
+	}
+
+	#[test]
+	fn next_ready_per_sender_should_return_lowest_nonces() {
+		let pool = pool();
+		let bob_nonce = index(&BlockId::number(0), Bob.to_raw_public().into());
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 210)).unwrap();
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 209)).unwrap();
+		pool.submit_one(&BlockId::number(0), uxt(Bob, bob_nonce)).unwrap();
+
+		let mut next: Vec<_> = pool.next_ready_per_sender(&BlockId::number(0)).iter()
+			.map(|xt| (*xt.sender(), xt.original.transfer.nonce))
+			.collect();
+		next.sort();
+
+		let mut expected = vec![(Alice.to_raw_public().into(), 209), (Bob.to_raw_public().into(), bob_nonce)];
+		expected.sort();
+		assert_eq!(next, expected);
+	}
+
+	#[test]
+	fn ages_should_grow_while_extrinsics_wait() {
+		let pool = pool();
+		let hash = *pool.submit_one(&BlockId::number(0), uxt(Alice, 209)).unwrap().hash();
+
+		::std::thread::sleep(::std::time::Duration::from_millis(10));
+		let first = pool.ages();
+		::std::thread::sleep(::std::time::Duration::from_millis(10));
+		let second = pool.ages();
+
+		assert_eq!(first.len(), 1);
+		assert_eq!(first[0].0, hash);
+		assert!(first[0].1 > Default::default());
+		assert!(second[0].1 > first[0].1);
+	}
+
+	#[test]
+	fn should_requeue_quarantined_extrinsics() {
+		let pool = pool();
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 209)).unwrap();
+		let hash = pool.submit_one(&BlockId::number(0), uxt(Alice, 210)).unwrap().verified.hash;
+
+		// culled while building a block, e.g. because it was checked out of order.
+		pool.remove(&[hash], false);
+		assert!(pool.submit_one(&BlockId::number(0), uxt(Alice, 210)).is_err());
+
+		assert_eq!(pool.requeue_quarantined(&BlockId::number(0)), 1);
+		let pending: Vec<_> = pool.cull_and_get_pending(&BlockId::number(0), |p| p.map(|a| a.original.transfer.nonce).collect()).unwrap();
+		assert_eq!(pending, vec![209, 210]);
+		assert_eq!(pool.requeue_quarantined(&BlockId::number(0)), 0);
+	}
+
+	#[test]
+	fn should_record_removal_reason() {
+		let pool = pool();
+		let culled = pool.submit_one(&BlockId::number(0), uxt(Alice, 209)).unwrap().verified.hash;
+		let invalid = pool.submit_one(&BlockId::number(0), uxt(Alice, 210)).unwrap().verified.hash;
+		let watcher = pool.submit_and_watch(&BlockId::number(0), uxt(Alice, 211)).unwrap();
+		let watched = BlakeTwo256::hash(&uxt(Alice, 211).encode());
+
+		pool.remove_with_reason(&[culled, watched], RemovalReason::InvalidDuringProposal);
+		pool.remove(&[invalid], false);
+
+		assert_eq!(pool.removal_reason(&culled), Some(RemovalReason::InvalidDuringProposal));
+		assert_eq!(pool.removal_reason(&invalid), Some(RemovalReason::Invalid));
+		assert_eq!(pool.light_status().transaction_count, 0);
+
+		let mut stream = watcher.into_stream().wait();
+		match stream.next() {
+			Some(Ok(::watcher::Status::Invalid(reason))) => assert_eq!(reason, RemovalReason::InvalidDuringProposal),
+			other => panic!("unexpected status: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn should_replace_all_extrinsics() {
+		let pool = pool();
+		pool.submit_at(&BlockId::number(0), vec![uxt(Alice, 209), uxt(Alice, 210)]).unwrap();
+		let mut forged = uxt(Bob, 1);
+		forged.transfer.amount = 2;
+
+		let results = pool.replace_all(&BlockId::number(0), vec![uxt(Alice, 211), forged, uxt(Bob, 2)]);
+
+		assert_eq!(results.iter().map(Result::is_ok).collect::<Vec<_>>(), vec![true, false, true]);
+		let mut nonces: Vec<_> = pool.all().values()
+			.flat_map(|xts| xts.iter().map(|xt| xt.transfer.nonce))
+			.collect();
+		nonces.sort();
+		assert_eq!(nonces, vec![2, 211]);
+	}
+
+	#[test]
+	fn should_stay_usable_after_panic_with_lock_held() {
+		use std::panic::{self, AssertUnwindSafe};
+
+		let pool = pool();
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 209)).unwrap();
+		let bad = pool.submit_one(&BlockId::number(0), uxt(Bob, PANICKING_NONCE)).unwrap().verified.hash;
+
+		// the pool lock is held while checking readiness.
+		let result = panic::catch_unwind(AssertUnwindSafe(|| pool.pending(&BlockId::number(0), |p| p.count())));
+		assert!(result.is_err());
+
+		pool.remove(&[bad], false);
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 210)).unwrap();
+		let pending: Vec<_> = pool.cull_and_get_pending(&BlockId::number(0), |p| p.map(|a| a.original.transfer.nonce).collect()).unwrap();
+		assert_eq!(pending, vec![209, 210]);
+	}
+
+	#[test]
+	fn should_partition_status_by_readiness() {
+		let pool = pool();
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 209)).unwrap();
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 210)).unwrap();
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 212)).unwrap();
+
+		assert_eq!(pool.partitioned_status(&BlockId::number(0)), PartitionedStatus {
+			ready: 2,
+			future: 1,
+			total_bytes: 3 * uxt(Alice, 209).encode().len(),
+		});
+	}
+
+	#[test]
+	fn snapshot_should_restore_valid_extrinsics() {
+		let pool = pool();
+		pool.submit_one(&BlockId::number(0), uxt(Alice, 209)).unwrap();
+		pool.submit_one(&BlockId::number(0), uxt(Bob, 5)).unwrap();
+		let mut snapshot = pool.snapshot();
+		let mut forged = uxt(Charlie, 0);
+		forged.transfer.amount = 2;
+		snapshot.push(forged.encode());
+		snapshot.push(vec![1, 2, 3]);
+
+		let restarted = pool();
+		assert_eq!(restarted.restore(snapshot, &BlockId::number(0)), 2);
+
+		let mut all: Vec<_> = restarted.all().into_iter().flat_map(|(_, xts)| xts).map(|xt| xt.transfer.nonce).collect();
+		all.sort();
+		assert_eq!(all, vec![5, 209]);
 	}
 }
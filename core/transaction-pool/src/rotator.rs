@@ -32,6 +32,15 @@ use Verified;
 /// Expected size of the banned extrinsics cache.
 const EXPECTED_SIZE: usize = 2048;
 
+/// Why an extrinsic is banned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ban {
+	/// It occupied the pool for too long.
+	Stale,
+	/// It was found to be invalid.
+	Invalid,
+}
+
 /// Pool rotator is responsible to only keep fresh extrinsics in the pool.
 ///
 /// Extrinsics that occupy the pool for too long are culled and temporarily banned from entering
@@ -40,7 +49,7 @@ pub struct PoolRotator<Hash> {
 	/// How long the extrinsic is banned for.
 	ban_time: Duration,
 	/// Currently banned extrinsics.
-	banned_until: RwLock<HashMap<Hash, Instant>>,
+	banned_until: RwLock<HashMap<Hash, (Instant, Ban)>>,
 }
 
 impl<Hash: hash::Hash + Eq> Default for PoolRotator<Hash> {
@@ -58,12 +67,35 @@ impl<Hash: hash::Hash + Eq + Clone> PoolRotator<Hash> {
 		self.banned_until.read().contains_key(hash)
 	}
 
+	/// Returns why extrinsic hash is currently banned, if it is.
+	pub fn ban_of(&self, hash: &Hash) -> Option<Ban> {
+		self.banned_until.read().get(hash).map(|&(_, ban)| ban)
+	}
+
 	/// Bans given set of hashes.
 	pub fn ban(&self, now: &Instant, hashes: &[Hash]) {
+		self.ban_as(now, hashes, Ban::Stale)
+	}
+
+	/// Bans given set of hashes of extrinsics found to be invalid.
+	pub fn ban_invalid(&self, now: &Instant, hashes: &[Hash]) {
+		self.ban_as(now, hashes, Ban::Invalid)
+	}
+
+	/// Lifts the ban of given set of hashes.
+	pub fn unban(&self, hashes: &[Hash]) {
 		let mut banned = self.banned_until.write();
 
 		for hash in hashes {
-			banned.insert(hash.clone(), *now + self.ban_time);
+			banned.remove(hash);
+		}
+	}
+
+	fn ban_as(&self, now: &Instant, hashes: &[Hash], ban: Ban) {
+		let mut banned = self.banned_until.write();
+
+		for hash in hashes {
+			banned.insert(hash.clone(), (*now + self.ban_time, ban));
 		}
 
 		if banned.len() > 2 * EXPECTED_SIZE {
@@ -94,7 +126,7 @@ impl<Hash: hash::Hash + Eq + Clone> PoolRotator<Hash> {
 	pub fn clear_timeouts(&self, now: &Instant) {
 		let mut banned = self.banned_until.write();
 
-		banned.retain(|_, &mut v| v >= *now);
+		banned.retain(|_, &mut (until, _)| until >= *now);
 	}
 }
 
@@ -121,6 +153,7 @@ mod tests {
 				nonce: Default::default(),
 			},
 			valid_till: Instant::now(),
+			imported_at: Instant::now(),
 		};
 
 		(hash, tx)
@@ -172,6 +205,25 @@ mod tests {
 		assert!(!rotator.is_banned(&hash));
 	}
 
+	#[test]
+	fn should_tell_invalid_bans_apart() {
+		// given
+		let (stale, tx) = tx();
+		let invalid: Hash = 6.into();
+		let rotator = rotator();
+		let now = Instant::now();
+
+		// when
+		assert!(rotator.ban_if_stale(&now, &tx));
+		rotator.ban_invalid(&now, &[invalid]);
+
+		// then
+		assert_eq!(rotator.ban_of(&stale), Some(Ban::Stale));
+		assert_eq!(rotator.ban_of(&invalid), Some(Ban::Invalid));
+		rotator.clear_timeouts(&(now + rotator.ban_time + rotator.ban_time));
+		assert_eq!(rotator.ban_of(&invalid), None);
+	}
+
 	#[test]
 	fn should_garbage_collect() {
 		// given
@@ -185,6 +237,7 @@ mod tests {
 					nonce: Default::default(),
 				},
 				valid_till: time,
+				imported_at: time,
 			}
 		}
 
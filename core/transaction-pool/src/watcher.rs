@@ -21,6 +21,16 @@ use futures::{
 	sync::mpsc,
 };
 
+/// Why an extrinsic was removed from the pool as invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RemovalReason {
+	/// The extrinsic was found to be invalid.
+	Invalid,
+	/// The extrinsic failed to apply while a block was being proposed.
+	InvalidDuringProposal,
+}
+
 /// Possible extrinsic status events
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -33,6 +43,8 @@ pub enum Status<H> {
 	Broadcast(Vec<String>),
 	/// Extrinsic has been dropped from the pool because of the limit.
 	Dropped,
+	/// Extrinsic has been removed from the pool as invalid.
+	Invalid(RemovalReason),
 }
 
 /// Extrinsic watcher.
@@ -91,6 +103,11 @@ impl<H: Clone> Sender<H> {
 		self.send(Status::Broadcast(peers))
 	}
 
+	/// Extrinsic has been removed from the pool as invalid.
+	pub fn invalid(&mut self, reason: RemovalReason) {
+		self.send(Status::Invalid(reason))
+	}
+
 
 	/// Returns true if the are no more listeners for this extrinsic or it was finalised.
 	pub fn is_done(&self) -> bool {
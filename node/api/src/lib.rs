@@ -24,6 +24,7 @@ extern crate node_runtime as runtime;
 extern crate substrate_client as client;
 extern crate sr_primitives;
 extern crate substrate_primitives;
+extern crate parity_codec as codec;
 
 pub use client::error::{Error, ErrorKind, Result};
 use runtime::Address;
@@ -31,7 +32,7 @@ use client::backend::Backend;
 use client::block_builder::BlockBuilder as ClientBlockBuilder;
 use client::{Client, CallExecutor};
 use primitives::{
-	AccountId, Block, BlockId, BlockNumber, Hash, Index, InherentData, SessionKey, Timestamp, UncheckedExtrinsic
+	AccountId, Balance, Block, BlockId, BlockNumber, Hash, Index, InherentData, SessionKey, Timestamp, UncheckedExtrinsic
 };
 use sr_primitives::{transaction_validity::TransactionValidity, traits::{CurrentHeight, BlockNumberToHash}};
 use substrate_primitives::{Blake2Hasher, RlpCodec};
@@ -52,15 +53,58 @@ pub trait Api: CurrentHeight<BlockNumber=BlockNumber> + BlockNumberToHash<BlockN
 	/// The block builder for this API type.
 	type BlockBuilder: BlockBuilder;
 
+	/// Get the id of the current best block.
+	fn best_block_id(&self) -> Result<BlockId> {
+		Ok(BlockId::number(self.current_height()))
+	}
+
+	/// Get the hash of the genesis block, which immortal transactions are anchored to.
+	fn genesis_block_hash(&self) -> Result<Hash>;
+
+	/// Get the spec name of the runtime active at the given block.
+	fn chain_spec_name(&self, at: &BlockId) -> Result<String>;
+
+	/// Whether the block with the given id is known.
+	fn has_block(&self, at: &BlockId) -> Result<bool>;
+
 	/// Get session keys at a given block.
 	fn session_keys(&self, at: &BlockId) -> Result<Vec<SessionKey>>;
 
 	/// Get validators at a given block.
 	fn validators(&self, at: &BlockId) -> Result<Vec<AccountId>>;
 
+	/// Get the index of the session a given block is in.
+	///
+	/// Fails with `ApiNotImplemented` if the runtime doesn't expose it.
+	fn session_index(&self, at: &BlockId) -> Result<BlockNumber>;
+
+	/// Get validators paired with their session keys at a given block.
+	fn validator_set(&self, at: &BlockId) -> Result<Vec<(AccountId, SessionKey)>> {
+		pair_session_keys(self.validators(at)?, self.session_keys(at)?)
+	}
+
 	/// Get the value of the randomness beacon at a given block.
+	///
+	/// Fails with `ApiNotImplemented` if the runtime has no randomness beacon.
 	fn random_seed(&self, at: &BlockId) -> Result<Hash>;
 
+	/// Get the randomness beacon and validators at a given block together.
+	///
+	/// Runtimes which support it serve both with a single call.
+	fn consensus_context(&self, at: &BlockId) -> Result<(Hash, Vec<AccountId>)> {
+		Ok((self.random_seed(at)?, self.validators(at)?))
+	}
+
+	/// Get the maximum encoded length of the transactions in a block built on the given.
+	///
+	/// Fails with `ApiNotImplemented` if the runtime doesn't report one.
+	fn max_block_length(&self, at: &BlockId) -> Result<usize>;
+
+	/// Get the reward the author of a block built on the given would earn.
+	///
+	/// Fails with `ApiNotImplemented` if the runtime doesn't pay authors.
+	fn author_reward(&self, at: &BlockId) -> Result<Balance>;
+
 	/// Get the timestamp registered at a block.
 	fn timestamp(&self, at: &BlockId) -> Result<Timestamp>;
 
@@ -69,6 +113,18 @@ pub trait Api: CurrentHeight<BlockNumber=BlockNumber> + BlockNumberToHash<BlockN
 	/// Get the nonce (né index) of an account at a block.
 	fn index(&self, at: &BlockId, account: AccountId) -> Result<Index>;
 
+	/// Get the free balance of an account at a block.
+	fn balance(&self, at: &BlockId, account: AccountId) -> Result<Balance>;
+
+	/// Whether an account has any on-chain presence at a block, i.e. it has sent a
+	/// transaction or holds a balance.
+	fn account_exists(&self, at: &BlockId, account: AccountId) -> Result<bool> {
+		Ok(self.index(at, account)? > 0 || self.balance(at, account)? > 0)
+	}
+
+	/// Get the balance at stake for a validator, including its nominators, at a block.
+	fn stake_of(&self, at: &BlockId, account: AccountId) -> Result<Balance>;
+
 	/// Get the account id of an address at a block.
 	fn lookup(&self, at: &BlockId, address: Address) -> Result<Option<AccountId>>;
 
@@ -82,9 +138,90 @@ pub trait Api: CurrentHeight<BlockNumber=BlockNumber> + BlockNumberToHash<BlockN
 	/// Build a block on top of the given, with inherent extrinsics pre-pushed.
 	fn build_block(&self, at: &BlockId, inherent_data: InherentData) -> Result<Self::BlockBuilder>;
 
+	/// Check whether an extrinsic could be pushed onto the block being built, without
+	/// modifying the block builder. The extrinsic is validated but not dispatched, so pushing
+	/// it may still fail.
+	fn would_apply(&self, builder: &Self::BlockBuilder, extrinsic: &UncheckedExtrinsic) -> Result<bool>;
+
 	/// Attempt to produce the (encoded) inherent extrinsics for a block being built upon the given.
 	/// This may vary by runtime and will fail if a runtime doesn't follow the same API.
 	fn inherent_extrinsics(&self, at: &BlockId, inherent_data: InherentData) -> Result<Vec<UncheckedExtrinsic>>;
+
+	/// Compute the extrinsics root a block's header should commit to.
+	fn expected_extrinsics_root(&self, block: &Block) -> Hash {
+		use codec::Encode;
+		use sr_primitives::traits::{BlakeTwo256, Hash as HashT};
+
+		BlakeTwo256::ordered_trie_root(block.extrinsics.iter().map(Encode::encode))
+	}
+
+	/// Check whether the inherent extrinsics of a block built upon the given are exactly those
+	/// which would be produced from the given inherent data, and that no other unsigned
+	/// extrinsics follow them.
+	///
+	/// This says nothing about the inherent data itself; callers have to derive it or
+	/// check it independently of the block, e.g. bound the timestamp and check offline
+	/// reports against their own view.
+	///
+	/// Fails with `ApiNotImplemented` if the runtime doesn't produce inherent extrinsics.
+	fn check_inherents(&self, at: &BlockId, block: &Block, inherent_data: &InherentData) -> Result<bool> {
+		let expected = self.inherent_extrinsics(at, inherent_data.clone())?;
+		Ok(inherents_match(block, &expected))
+	}
+
+	/// Reconstruct the inherent data of a block from its leading inherent extrinsics.
+	///
+	/// Fails if the block doesn't start with a timestamp inherent.
+	fn extract_inherents(&self, block: &Block) -> Result<InherentData> {
+		use codec::{Decode, Encode};
+
+		let block = runtime::Block::decode(&mut block.encode().as_slice())
+			.and_then(|block| runtime::CheckedBlock::new(block).ok())
+			.ok_or_else(|| Error::from("Block does not start with a timestamp inherent"))?;
+
+		Ok(InherentData {
+			timestamp: block.timestamp(),
+			offline_indices: block.noted_offline().to_vec(),
+		})
+	}
+}
+
+/// Pair validators with their session keys, failing if there isn't exactly one key for each.
+pub fn pair_session_keys(validators: Vec<AccountId>, session_keys: Vec<SessionKey>) -> Result<Vec<(AccountId, SessionKey)>> {
+	if validators.len() != session_keys.len() {
+		return Err(ErrorKind::InvalidAuthoritiesSet.into());
+	}
+
+	Ok(validators.into_iter().zip(session_keys).collect())
+}
+
+/// Whether the block starts with the expected inherent extrinsics and carries no
+/// further unsigned extrinsics after them.
+fn inherents_match(block: &Block, expected: &[UncheckedExtrinsic]) -> bool {
+	use codec::{Decode, Encode};
+
+	if block.extrinsics.len() < expected.len() || block.extrinsics[..expected.len()] != expected[..] {
+		return false;
+	}
+
+	block.extrinsics[expected.len()..].iter().all(|xt| {
+		runtime::UncheckedExtrinsic::decode(&mut xt.encode().as_slice())
+			.map_or(false, |xt| xt.is_signed())
+	})
+}
+
+/// Turn an execution error caused by the runtime lacking `method` into `ApiNotImplemented`.
+fn not_implemented(err: Error, method: &'static str) -> Error {
+	let missing = match *err.kind() {
+		ErrorKind::Execution(ref e) => e.is_method_not_found(),
+		_ => false,
+	};
+
+	if missing {
+		ErrorKind::ApiNotImplemented(method).into()
+	} else {
+		err
+	}
 }
 
 impl<B, E> BlockBuilder for ClientBlockBuilder<B, E, Block, Blake2Hasher, RlpCodec>
@@ -109,6 +246,22 @@ where
 {
 	type BlockBuilder = ClientBlockBuilder<B, E, Block, Blake2Hasher, RlpCodec>;
 
+	fn best_block_id(&self) -> Result<BlockId> {
+		Ok(BlockId::hash(self.info()?.chain.best_hash))
+	}
+
+	fn genesis_block_hash(&self) -> Result<Hash> {
+		Ok(self.info()?.chain.genesis_hash)
+	}
+
+	fn chain_spec_name(&self, at: &BlockId) -> Result<String> {
+		Ok(self.runtime_version_at(at)?.spec_name.to_string())
+	}
+
+	fn has_block(&self, at: &BlockId) -> Result<bool> {
+		Ok(self.header(at)?.is_some())
+	}
+
 	fn session_keys(&self, at: &BlockId) -> Result<Vec<SessionKey>> {
 		Ok(self.authorities_at(at)?)
 	}
@@ -117,8 +270,30 @@ where
 		self.call_api_at(at, "validators", &())
 	}
 
+	fn session_index(&self, at: &BlockId) -> Result<BlockNumber> {
+		self.call_api_at(at, "session_index", &()).map_err(|e| not_implemented(e, "session_index"))
+	}
+
 	fn random_seed(&self, at: &BlockId) -> Result<Hash> {
-		self.call_api_at(at, "random_seed", &())
+		self.call_api_at(at, "random_seed", &()).map_err(|e| not_implemented(e, "random_seed"))
+	}
+
+	fn consensus_context(&self, at: &BlockId) -> Result<(Hash, Vec<AccountId>)> {
+		if self.runtime_version_at(at)?.has_api(*b"consctxt", 1) {
+			self.call_api_at(at, "consensus_context", &())
+		} else {
+			Ok((self.random_seed(at)?, self.validators(at)?))
+		}
+	}
+
+	fn max_block_length(&self, at: &BlockId) -> Result<usize> {
+		let length: u32 = self.call_api_at(at, "max_block_length", &())
+			.map_err(|e| not_implemented(e, "max_block_length"))?;
+		Ok(length as usize)
+	}
+
+	fn author_reward(&self, at: &BlockId) -> Result<Balance> {
+		self.call_api_at(at, "author_reward", &()).map_err(|e| not_implemented(e, "author_reward"))
 	}
 
 	fn timestamp(&self, at: &BlockId) -> Result<Timestamp> {
@@ -140,6 +315,14 @@ where
 		self.call_api_at(at, "account_nonce", &account)
 	}
 
+	fn balance(&self, at: &BlockId, account: AccountId) -> Result<Balance> {
+		self.call_api_at(at, "free_balance", &account)
+	}
+
+	fn stake_of(&self, at: &BlockId, account: AccountId) -> Result<Balance> {
+		self.call_api_at(at, "stake_of", &account)
+	}
+
 	fn lookup(&self, at: &BlockId, address: Address) -> Result<Option<AccountId>> {
 		self.call_api_at(at, "lookup_address", &address)
 	}
@@ -160,8 +343,22 @@ where
 		Ok(block_builder)
 	}
 
+	fn would_apply(&self, builder: &Self::BlockBuilder, extrinsic: &UncheckedExtrinsic) -> Result<bool> {
+		builder.would_apply(extrinsic)
+	}
+
 	fn inherent_extrinsics(&self, at: &BlockId, inherent_data: InherentData) -> Result<Vec<UncheckedExtrinsic>> {
 		self.call_api_at(at, "inherent_extrinsics", &inherent_data)
 	}
+
+	fn check_inherents(&self, at: &BlockId, block: &Block, inherent_data: &InherentData) -> Result<bool> {
+		let runtime_version = self.runtime_version_at(at)?;
+		if !runtime_version.has_api(*b"inherent", 1) {
+			return Err(ErrorKind::ApiNotImplemented("inherent_extrinsics").into());
+		}
+
+		let expected = self.inherent_extrinsics(at, inherent_data.clone())?;
+		Ok(inherents_match(block, &expected))
+	}
 }
 
@@ -29,10 +29,22 @@ error_chain! {
 			description("Local account ID not a validator at this block."),
 			display("Local account ID ({:?}) not a validator at this block.", id),
 		}
+		UnknownParent(hash: ::node_primitives::Hash) {
+			description("Parent block is not known to the client."),
+			display("Parent block {:?} is not known to the client.", hash),
+		}
+		EvaluationCancelled {
+			description("Proposal evaluation was cancelled"),
+			display("Proposal evaluation was cancelled"),
+		}
 		PrematureDestruction {
 			description("Proposer destroyed before finishing proposing or evaluating"),
 			display("Proposer destroyed before finishing proposing or evaluating"),
 		}
+		ClockUnavailable(e: ::std::time::SystemTimeError) {
+			description("Local clock is set before the unix epoch."),
+			display("Local clock is unavailable: {}", e),
+		}
 		Timer(e: ::tokio::timer::Error) {
 			description("Failed to register or resolve async timer."),
 			display("Timer failed: {}", e),
@@ -41,6 +53,26 @@ error_chain! {
 			description("Unable to dispatch agreement future"),
 			display("Unable to dispatch agreement future: {:?}", e),
 		}
+		InvalidExtrinsic(hash: ::node_primitives::Hash, reason: String) {
+			description("Extrinsic could not be pushed onto the block."),
+			display("Extrinsic {:?} could not be pushed onto the block: {}", hash, reason),
+		}
+		MisbehaviorEncoding {
+			description("Misbehavior report does not encode as a valid node extrinsic."),
+			display("Misbehavior report does not encode as a valid node extrinsic."),
+		}
+		BadBake(reason: String) {
+			description("Proposed block failed structural checks after being built."),
+			display("Proposed block failed structural checks after being built: {}", reason),
+		}
+		ConcurrentProposal(parent: ::node_primitives::Hash) {
+			description("Already proposing on top of this parent."),
+			display("Already proposing on top of parent {}.", parent),
+		}
+		EmptyBlockSuppressed {
+			description("Not proposing an empty block this soon after the previous proposal."),
+			display("Not proposing an empty block this soon after the previous proposal."),
+		}
 	}
 }
 
@@ -49,3 +81,37 @@ impl From<::bft::InputStreamConcluded> for Error {
 		::bft::Error::from(err).into()
 	}
 }
+
+impl Error {
+	/// Whether the failed operation may succeed if retried.
+	pub fn is_retriable(&self) -> bool {
+		match *self.kind() {
+			ErrorKind::Api(ref e) => ::is_transient(e),
+			ErrorKind::Timer(_) | ErrorKind::EmptyBlockSuppressed => true,
+			ErrorKind::Executor(::futures::future::ExecuteErrorKind::NoCapacity) => true,
+			_ => false,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::future::ExecuteErrorKind;
+
+	#[test]
+	fn classifies_retriable_errors() {
+		let backend: Error = ::node_api::ErrorKind::Backend("database is locked".into()).into();
+		assert!(backend.is_retriable());
+		assert!(Error::from(ErrorKind::Timer(::tokio::timer::Error::shutdown())).is_retriable());
+		assert!(Error::from(ErrorKind::Executor(ExecuteErrorKind::NoCapacity)).is_retriable());
+		assert!(Error::from(ErrorKind::EmptyBlockSuppressed).is_retriable());
+
+		let unknown_block: Error = ::node_api::ErrorKind::UnknownBlock("0x00".into()).into();
+		assert!(!unknown_block.is_retriable());
+		assert!(!Error::from(ErrorKind::Executor(ExecuteErrorKind::Shutdown)).is_retriable());
+		assert!(!Error::from(ErrorKind::MisbehaviorEncoding).is_retriable());
+		assert!(!Error::from(ErrorKind::PrematureDestruction).is_retriable());
+		assert!(!Error::from(ErrorKind::NotValidator(Default::default())).is_retriable());
+	}
+}
@@ -18,6 +18,8 @@
 
 use super::MAX_TRANSACTIONS_SIZE;
 
+use std::time::Duration;
+
 use codec::{Decode, Encode};
 use node_runtime::{Block as GenericBlock, CheckedBlock};
 use node_primitives::{Block, Hash, BlockNumber, Timestamp};
@@ -56,13 +58,17 @@ error_chain! {
 
 /// Attempt to evaluate a substrate block as a node block, returning error
 /// upon any initial validity checks failing.
+///
+/// The block's timestamp may be at most `max_drift` seconds ahead of `now`, plus
+/// `skew_tolerance` to allow for clocks of honest nodes being slightly apart.
 pub fn evaluate_initial(
 	proposal: &Block,
 	now: Timestamp,
+	max_drift: Timestamp,
+	skew_tolerance: Duration,
 	parent_hash: &Hash,
 	parent_number: BlockNumber,
 ) -> Result<CheckedBlock> {
-	const MAX_TIMESTAMP_DRIFT: Timestamp = 60;
 
 	let encoded = Encode::encode(proposal);
 	let proposal = GenericBlock::decode(&mut &encoded[..])
@@ -88,7 +94,7 @@ pub fn evaluate_initial(
 	let block_timestamp = proposal.timestamp();
 
 	// lenient maximum -- small drifts will just be delayed using a timer.
-	if block_timestamp > now + MAX_TIMESTAMP_DRIFT {
+	if block_timestamp > now.saturating_add(max_drift).saturating_add(skew_tolerance.as_secs()) {
 		bail!(ErrorKind::TimestampInFuture)
 	}
 
@@ -42,24 +42,31 @@ extern crate log;
 #[cfg(test)]
 extern crate substrate_keyring;
 
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
 use std::sync::Arc;
+use std::thread;
 use std::time::{self, Duration, Instant};
 
 use codec::{Decode, Encode};
 use node_api::Api;
-use node_primitives::{AccountId, Hash, Block, BlockId, BlockNumber, Header, Timestamp, SessionKey};
+use node_primitives::{
+	AccountId, Balance, Hash, Block, BlockId, BlockNumber, Header, InherentData, Timestamp, SessionKey, Signature, UncheckedExtrinsic
+};
 use runtime_primitives::generic::Era;
 use primitives::{AuthorityId, ed25519};
-use transaction_pool::TransactionPool;
+use bft::EvaluationOutcome;
+use transaction_pool::{RemovalReason, TransactionPool};
 use tokio::runtime::TaskExecutor;
 use tokio::timer::Delay;
 
 use futures::prelude::*;
 use futures::future;
-use parking_lot::RwLock;
+use futures::sync::oneshot;
+use parking_lot::Mutex;
 
 pub use self::error::{ErrorKind, Error};
-pub use self::offline_tracker::OfflineTracker;
+pub use self::offline_tracker::{OfflineTracker, SharedOfflineTracker, Inconsistency};
 pub use service::Service;
 
 mod evaluation;
@@ -67,11 +74,33 @@ mod error;
 mod offline_tracker;
 mod service;
 
-/// Shared offline validator tracker.
-pub type SharedOfflineTracker = Arc<RwLock<OfflineTracker>>;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
-// block size limit.
-const MAX_TRANSACTIONS_SIZE: usize = 4 * 1024 * 1024;
+// block size limit, used unless the runtime reports a lower one.
+const MAX_TRANSACTIONS_SIZE: usize = node_primitives::SIZE_LIMITS.max_block_size;
+
+/// Default maximum number of offline validators reported in a single block.
+pub const DEFAULT_MAX_OFFLINE_REPORTS: usize = 16;
+
+/// Default maximum time spent evaluating a proposal.
+pub const DEFAULT_EVALUATE_DEADLINE: Duration = Duration::from_secs(30);
+
+/// Default maximum time a good vote is delayed until the proposal's timestamp is current.
+/// Proposals further in the future are invalid.
+pub const DEFAULT_MAX_VOTE_DELAY: Duration = Duration::from_secs(60);
+
+/// Default time the first round of an agreement may take.
+pub const DEFAULT_ROUND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default tolerance for proposal timestamps ahead of the local clock.
+pub const DEFAULT_SKEW_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Default number of times block evaluation is retried after a transient error.
+pub const DEFAULT_EVALUATE_RETRIES: usize = 2;
+
+/// Default number of proposal evaluations executing at once.
+pub const DEFAULT_EVALUATION_PERMITS: usize = 2;
 
 /// A long-lived network which can create BFT message routing processes on demand.
 pub trait Network {
@@ -91,6 +120,158 @@ pub trait Network {
 	) -> (Self::Input, Self::Output);
 }
 
+/// Signs the reports a proposer submits on behalf of the local authority.
+pub trait Signer: Send + Sync {
+	/// The account signed reports are attributed to.
+	fn public(&self) -> AccountId;
+
+	/// Sign a message.
+	fn sign(&self, message: &[u8]) -> Signature;
+}
+
+impl Signer for ed25519::Pair {
+	fn public(&self) -> AccountId {
+		ed25519::Pair::public(self).0.into()
+	}
+
+	fn sign(&self, message: &[u8]) -> Signature {
+		ed25519::Pair::sign(self, message).into()
+	}
+}
+
+/// Supplies inherent data beyond the timestamp and offline reports gathered by the proposer.
+pub trait InherentProvider: Send + Sync {
+	/// Merge additional inherent data into what the proposer gathered on top of `parent`.
+	fn provide_inherent_data(&self, parent: &BlockId, inherent_data: &mut InherentData);
+}
+
+/// Vetoes proposals carrying unacceptable inherent data, beyond the checks of the proposer.
+pub trait InherentValidator: Send + Sync {
+	/// Check the inherent data decoded from a proposal on top of `parent`, returning
+	/// why it is unacceptable if it is.
+	fn validate_inherents(&self, parent: &BlockId, inherent_data: &InherentData) -> Result<(), String>;
+}
+
+/// Decides which pending transactions that fit into a proposal are included.
+pub trait InclusionPolicy: Send + Sync {
+	/// Whether to include `extrinsic`, of `encoded_size` bytes, given `remaining` bytes of
+	/// block capacity.
+	fn include(&self, extrinsic: &UncheckedExtrinsic, encoded_size: usize, remaining: usize) -> bool;
+}
+
+/// Observes the outcome of consensus rounds, e.g. to record round statistics.
+pub trait RoundObserver: Send + Sync {
+	/// Called when a round ends, with whether a block was proposed in it and the
+	/// validator who was due to propose.
+	fn on_round_end(&self, round_number: usize, was_proposed: bool, primary_validator: AccountId);
+
+	/// Called after proposing a block with the reward its author would earn, if the
+	/// runtime reports one.
+	fn on_author_reward(&self, _block_number: BlockNumber, _reward: Balance) {}
+}
+
+/// Order in which pending transactions are packed into a proposal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InclusionMode {
+	/// Pack transactions in the order the pool yields them.
+	Greedy,
+	/// Take one transaction per sender in turn, so no sender dominates the block.
+	RoundRobin,
+}
+
+impl Default for InclusionMode {
+	fn default() -> Self {
+		InclusionMode::Greedy
+	}
+}
+
+impl InclusionMode {
+	// Order the pending transactions, keyed by `sender`, for packing.
+	fn order<'a, I, K, F>(self, pending: I, sender: F) -> Box<Iterator<Item=I::Item> + 'a>
+		where
+			I: Iterator + 'a,
+			K: Eq + ::std::hash::Hash,
+			F: Fn(&I::Item) -> K,
+	{
+		match self {
+			InclusionMode::Greedy => Box::new(pending),
+			InclusionMode::RoundRobin => Box::new(interleave_by(pending, sender).into_iter()),
+		}
+	}
+}
+
+/// Validator set of the session a proposer was most recently started in.
+///
+/// Keyed by parent hash alone when the runtime doesn't report session indices.
+#[derive(Default)]
+pub struct ValidatorCache {
+	entry: Mutex<Option<((Hash, Option<BlockNumber>), Vec<(AccountId, SessionKey)>)>>,
+}
+
+impl ValidatorCache {
+	// The validator set for the session with the given parent, fetching it if not cached.
+	fn get_or_fetch<F, E>(&self, key: (Hash, Option<BlockNumber>), fetch: F) -> ::std::result::Result<Vec<(AccountId, SessionKey)>, E>
+		where F: FnOnce() -> ::std::result::Result<Vec<(AccountId, SessionKey)>, E>
+	{
+		let mut entry = self.entry.lock();
+		match *entry {
+			Some((ref cached_key, ref validators)) if *cached_key == key => return Ok(validators.clone()),
+			_ => {}
+		}
+
+		let validators = fetch()?;
+		*entry = Some((key, validators.clone()));
+		Ok(validators)
+	}
+}
+
+type EvaluationJob = Box<FnMut() + Send>;
+
+/// Executes proposal evaluations on a fixed number of threads shared by the proposers
+/// using it. Evaluations beyond the limit queue for a free thread, and are skipped if
+/// nobody waits for them anymore by the time one is free, e.g. past their deadline.
+///
+/// An evaluation which is already executing runs to completion.
+#[derive(Clone)]
+pub struct EvaluationLimit(Arc<Mutex<::std::sync::mpsc::Sender<EvaluationJob>>>);
+
+impl EvaluationLimit {
+	/// Execute up to `permits` evaluations at once.
+	pub fn new(permits: usize) -> Self {
+		let (sender, receiver) = ::std::sync::mpsc::channel::<EvaluationJob>();
+		let receiver = Arc::new(Mutex::new(receiver));
+		for _ in 0..::std::cmp::max(permits, 1) {
+			let receiver = receiver.clone();
+			// the threads exit once the limit and all its clones are dropped.
+			thread::spawn(move || loop {
+				let job = receiver.lock().recv();
+				match job {
+					Ok(mut job) => {
+						// a panicking evaluation resolves as a premature destruction instead.
+						let _ = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| job()));
+					}
+					Err(_) => break,
+				}
+			});
+		}
+
+		EvaluationLimit(Arc::new(Mutex::new(sender)))
+	}
+
+	// Queue `f` to be executed once a thread is free.
+	fn execute<F: FnOnce() + Send + 'static>(&self, f: F) {
+		let mut f = Some(f);
+		let job: EvaluationJob = Box::new(move || if let Some(f) = f.take() { f() });
+		let _ = self.0.lock().send(job);
+	}
+}
+
+impl Default for EvaluationLimit {
+	fn default() -> Self {
+		EvaluationLimit::new(DEFAULT_EVALUATION_PERMITS)
+	}
+}
+
 /// Proposer factory.
 pub struct ProposerFactory<N, P>
 	where
@@ -106,6 +287,43 @@ pub struct ProposerFactory<N, P>
 	pub handle: TaskExecutor,
 	/// Offline-tracker.
 	pub offline: SharedOfflineTracker,
+	/// Whether to report offline validators in proposals and check the reports of others.
+	pub report_offline: bool,
+	/// Maximum number of offline validators reported in a single block.
+	pub max_offline_reports: usize,
+	/// Minimum time since the previous block carrying transactions before an empty block
+	/// is proposed. Empty blocks are always proposed if this is `None`.
+	pub min_empty_interval: Option<Duration>,
+	/// When the last block carrying transactions was proposed.
+	pub last_proposal: Arc<Mutex<Instant>>,
+	/// Parents a block is being proposed on top of right now.
+	pub proposing: Arc<Mutex<HashSet<Hash>>>,
+	/// Maximum time spent evaluating a proposal before voting against it.
+	pub evaluate_deadline: Duration,
+	/// Number of times block evaluation is retried after a transient error.
+	pub evaluate_retries: usize,
+	/// Threads executing evaluations, shared by all proposers.
+	pub evaluation_limit: EvaluationLimit,
+	/// Maximum time a good vote is delayed until the proposal's timestamp is current.
+	/// Proposals further in the future are invalid.
+	pub max_vote_delay: Duration,
+	/// How far ahead of the local clock a proposal's timestamp may be and still count as current.
+	pub skew_tolerance: Duration,
+	/// Additional inherent data to include in proposed blocks.
+	pub inherent_provider: Option<Arc<InherentProvider>>,
+	/// Validator of the inherent data of proposals. Only the built-in checks are made if `None`.
+	pub inherent_validator: Option<Arc<InherentValidator>>,
+	/// Policy vetoing transactions during packing. Everything that fits is included if `None`.
+	pub inclusion_policy: Option<Arc<InclusionPolicy>>,
+	/// Order in which pending transactions are packed.
+	pub inclusion_mode: InclusionMode,
+	/// Maximum number of transactions packed into a block, besides inherents.
+	/// Only the block size limits packing if `None`.
+	pub max_extrinsics: Option<usize>,
+	/// Notified of the outcome of every round.
+	pub round_observer: Option<Arc<RoundObserver>>,
+	/// Validator set of the most recent session.
+	pub validator_cache: ValidatorCache,
 }
 
 impl<N, P> bft::Environment<Block> for ProposerFactory<N, P>
@@ -132,15 +350,47 @@ impl<N, P> bft::Environment<Block> for ProposerFactory<N, P>
 		let parent_hash = parent_header.hash().into();
 
 		let id = BlockId::hash(parent_hash);
-		let random_seed = self.client.random_seed(&id)?;
-		let random_seed = BlakeTwo256::hash(&*random_seed);
+		if !self.client.has_block(&id)? {
+			bail!(ErrorKind::UnknownParent(parent_hash));
+		}
+
+		let session_index = match self.client.session_index(&id) {
+			Ok(index) => Some(index),
+			Err(node_api::Error(node_api::ErrorKind::ApiNotImplemented(_), _)) => None,
+			Err(e) => return Err(e.into()),
+		};
+		let mut random_seed = None;
+		let validator_set = self.validator_cache.get_or_fetch((parent_hash, session_index), || {
+			// the randomness beacon comes along with the validators where the runtime supports it.
+			match self.client.consensus_context(&id) {
+				Ok((seed, validators)) => {
+					random_seed = Some(seed);
+					node_api::pair_session_keys(validators, self.client.session_keys(&id)?)
+				}
+				Err(node_api::Error(node_api::ErrorKind::ApiNotImplemented(_), _)) => self.client.validator_set(&id),
+				Err(e) => Err(e),
+			}
+		})?;
 
-		let validators = self.client.validators(&id)?;
-		self.offline.write().note_new_block(&validators[..]);
+		let random_seed = match random_seed.map_or_else(|| self.client.random_seed(&id), Ok) {
+			Ok(seed) => seed,
+			Err(node_api::Error(node_api::ErrorKind::ApiNotImplemented(_), _)) => {
+				warn!("Runtime has no randomness beacon; deriving seed from parent hash {:?}", parent_hash);
+				parent_hash
+			}
+			Err(e) => return Err(e.into()),
+		};
+		let random_seed = BlakeTwo256::hash(&*random_seed);
+		let validators: Vec<AccountId> = validator_set.iter().map(|&(v, _)| v).collect();
+		self.offline.with_mut(|offline| offline.note_new_block(&validators[..]));
 
-		info!("Starting consensus session on top of parent {:?}", parent_hash);
+		let spec_name = self.client.chain_spec_name(&id)?;
+		info!("Starting consensus session of {} on top of parent {:?}", spec_name, parent_hash);
 
-		let local_id = sign_with.public().0.into();
+		let local_account: AccountId = sign_with.public().0.into();
+		let local_id = validator_set.iter()
+			.find(|&&(v, _)| v == local_account)
+			.map_or_else(|| sign_with.public().0.into(), |&(_, key)| key);
 		let (input, output) = self.network.communication_for(
 			authorities,
 			local_id,
@@ -158,110 +408,361 @@ impl<N, P> bft::Environment<Block> for ProposerFactory<N, P>
 			random_seed,
 			transaction_pool: self.transaction_pool.clone(),
 			offline: self.offline.clone(),
+			report_offline: self.report_offline,
+			max_offline_reports: self.max_offline_reports,
+			min_empty_interval: self.min_empty_interval,
+			last_proposal: self.last_proposal.clone(),
+			proposing: self.proposing.clone(),
+			evaluate_deadline: self.evaluate_deadline,
+			evaluate_retries: self.evaluate_retries,
+			evaluation_limit: self.evaluation_limit.clone(),
+			max_vote_delay: self.max_vote_delay,
+			skew_tolerance: self.skew_tolerance,
+			inherent_provider: self.inherent_provider.clone(),
+			inherent_validator: self.inherent_validator.clone(),
+			inclusion_policy: self.inclusion_policy.clone(),
+			inclusion_mode: self.inclusion_mode,
+			max_extrinsics: self.max_extrinsics,
+			round_observer: self.round_observer.clone(),
+			evaluated: Default::default(),
+			last_block_bytes: Mutex::new(0),
+			last_counts: Mutex::new(ProposalCounts::default()),
+			reported_misbehavior: Default::default(),
+			cancellation: Default::default(),
 			validators,
-			minimum_timestamp: current_timestamp() + FORCE_DELAY,
+			minimum_timestamp: current_timestamp_checked()? + FORCE_DELAY,
 		};
 
 		Ok((proposer, input, output))
 	}
 }
 
+/// Transaction counts of a proposed block.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProposalCounts {
+	/// Number of transactions in the pool when the block was proposed.
+	pub pending_total: usize,
+	/// Number of pool transactions included in the block.
+	pub included: usize,
+}
+
 /// The proposer logic.
-pub struct Proposer<C: Api + Send + Sync> {
+pub struct Proposer<C: Api + Send + Sync, S: Signer = ed25519::Pair> {
 	client: Arc<C>,
 	start: Instant,
-	local_key: Arc<ed25519::Pair>,
+	local_key: Arc<S>,
 	parent_hash: Hash,
 	parent_id: BlockId,
 	parent_number: BlockNumber,
 	random_seed: Hash,
 	transaction_pool: Arc<TransactionPool<C>>,
 	offline: SharedOfflineTracker,
+	report_offline: bool,
+	max_offline_reports: usize,
+	min_empty_interval: Option<Duration>,
+	last_proposal: Arc<Mutex<Instant>>,
+	proposing: Arc<Mutex<HashSet<Hash>>>,
+	evaluate_deadline: Duration,
+	evaluate_retries: usize,
+	evaluation_limit: EvaluationLimit,
+	max_vote_delay: Duration,
+	skew_tolerance: Duration,
+	inherent_provider: Option<Arc<InherentProvider>>,
+	inherent_validator: Option<Arc<InherentValidator>>,
+	inclusion_policy: Option<Arc<InclusionPolicy>>,
+	inclusion_mode: InclusionMode,
+	max_extrinsics: Option<usize>,
+	round_observer: Option<Arc<RoundObserver>>,
+	evaluated: Arc<Mutex<HashMap<Hash, bool>>>,
+	last_block_bytes: Mutex<usize>,
+	last_counts: Mutex<ProposalCounts>,
+	// encoded misbehavior reports already submitted to the pool.
+	reported_misbehavior: Mutex<HashSet<Vec<u8>>>,
+	cancellation: Cancellation,
 	validators: Vec<AccountId>,
 	minimum_timestamp: u64,
 }
 
-impl<C: Api + Send + Sync> Proposer<C> {
+impl<C: Api + Send + Sync, S: Signer> Proposer<C, S> {
 	fn primary_index(&self, round_number: usize, len: usize) -> usize {
 		use primitives::uint::U256;
 
+		// a zero seed (e.g. from a runtime without a beacon) has no offset; rounds
+		// still rotate through all authorities, starting from the first.
+		if self.random_seed == Hash::default() {
+			return round_number % len;
+		}
+
 		let big_len = U256::from(len);
 		let offset = U256::from_big_endian(&self.random_seed.0) % big_len;
 		let offset = offset.low_u64() as usize + round_number;
 		offset % len
 	}
-}
 
-impl<C> bft::Proposer<Block> for Proposer<C>
-	where
-		C: Api + Send + Sync,
-{
-	type Create = Result<Block, Error>;
-	type Error = Error;
-	type Evaluate = Box<Future<Item=bool, Error=Error>>;
+	/// Abort the evaluations of this proposer still in progress, e.g. once its round is
+	/// over. They, and any started later, fail with `EvaluationCancelled`.
+	pub fn cancel_evaluations(&self) {
+		if let Some(signal) = self.cancellation.signal.lock().take() {
+			signal.fire();
+		}
+	}
 
-	fn propose(&self) -> Result<Block, Error> {
-		use node_api::BlockBuilder;
-		use runtime_primitives::traits::{Hash as HashT, BlakeTwo256};
-		use node_primitives::InherentData;
+	/// The validator set of the session, indexed as in offline reports.
+	pub fn validators(&self) -> &[AccountId] {
+		&self.validators
+	}
+
+	/// Hash and number of the block this proposer builds on.
+	pub fn parent(&self) -> (Hash, BlockNumber) {
+		(self.parent_hash, self.parent_number)
+	}
+
+	/// Whether the local authority is the proposer for the given round.
+	pub fn is_local_proposer(&self, round_number: usize, authorities: &[AuthorityId]) -> bool {
+		if authorities.is_empty() {
+			return false;
+		}
+
+		let offset = self.primary_index(round_number, authorities.len());
+		authorities[offset].0 == self.local_key.public().0
+	}
+
+	/// The authorities due to propose in the first `rounds` rounds, in order.
+	pub fn rotation_schedule(&self, authorities: &[AuthorityId], rounds: usize) -> Vec<AuthorityId> {
+		if authorities.is_empty() {
+			return Vec::new();
+		}
+
+		(0..rounds)
+			.map(|round| authorities[self.primary_index(round, authorities.len())])
+			.collect()
+	}
 
+	// Gather the inherent data for a block proposed on top of the parent.
+	fn inherent_data(&self) -> Result<InherentData, Error> {
 		const MAX_VOTE_OFFLINE_SECONDS: Duration = Duration::from_secs(60);
 
 		// TODO: handle case when current timestamp behind that in state.
-		let timestamp = ::std::cmp::max(self.minimum_timestamp, current_timestamp());
+		let timestamp = ::std::cmp::max(self.minimum_timestamp, current_timestamp_checked()?);
 
 		let elapsed_since_start = self.start.elapsed();
-		let offline_indices = if elapsed_since_start > MAX_VOTE_OFFLINE_SECONDS {
+		let offline_indices = if !self.report_offline || elapsed_since_start > MAX_VOTE_OFFLINE_SECONDS {
 			Vec::new()
 		} else {
-			self.offline.read().reports(&self.validators[..])
+			// slashing matters most for those with the most at stake.
+			let (validators, max) = (&self.validators[..], self.max_offline_reports);
+			self.offline.with(|offline| offline
+				.stake_weighted_reports(validators, max, |v| self.client.stake_of(&self.parent_id, *v))
+				.unwrap_or_else(|e| {
+					warn!(target: "consensus", "Unable to fetch stakes of offline validators; reporting those offline longest: {}", e);
+					offline.worst_reports(validators, max)
+				}))
 		};
 
 		if !offline_indices.is_empty() {
 			info!(
-				"Submitting offline validators {:?} for slash-vote",
+				"{} Submitting offline validators {:?} for slash-vote",
+				self.log_context(),
 				offline_indices.iter().map(|&i| self.validators[i as usize]).collect::<Vec<_>>(),
 				)
 		}
 
-		let inherent_data = InherentData {
+		let mut inherent_data = InherentData {
 			timestamp,
 			offline_indices,
 		};
+		if let Some(ref provider) = self.inherent_provider {
+			provider.provide_inherent_data(&self.parent_id, &mut inherent_data);
+		}
+
+		Ok(inherent_data)
+	}
+
+	/// Build a block on top of the parent from the given extrinsics, ignoring the
+	/// transaction pool. Inherents are included as usual; extrinsics which fail to
+	/// apply or don't fit in the block are left out.
+	pub fn propose_with(&self, extrinsics: Vec<UncheckedExtrinsic>) -> Result<Block, Error> {
+		use node_api::BlockBuilder;
+		use runtime_primitives::traits::{Hash as HashT, BlakeTwo256};
+
+		let mut block_builder = self.client.build_block(&self.parent_id, self.inherent_data()?)?;
+		let max_length = self.max_block_length();
+
+		let mut size = 0;
+		for extrinsic in extrinsics {
+			let encoded_size = extrinsic.encode().len();
+			if size + encoded_size >= max_length {
+				trace!(target: "bft", "{} Leaving out extrinsic of {} bytes; block is full", self.log_context(), encoded_size);
+				continue;
+			}
+
+			match push_extrinsic(&mut block_builder, BlakeTwo256::hash_of(&extrinsic), extrinsic) {
+				Ok(()) => size += encoded_size,
+				Err(e) => debug!(target: "bft", "{} {}", self.log_context(), e),
+			}
+		}
+
+		Ok(block_builder.bake()?)
+	}
+
+	/// Build a block from the pending transactions as `propose` would and evaluate it
+	/// against the parent, without proposing it or changing the transaction pool.
+	///
+	/// Returns the block along with whether it executes cleanly.
+	pub fn trial_propose(&self) -> Result<(Block, bool), Error> {
+		let pending: Vec<_> = self.transaction_pool.pending(&self.parent_id, |pending| pending.collect());
+		let extrinsics = self.inclusion_mode.order(pending.into_iter(), |pending| pending.verified.sender)
+			.take(self.max_extrinsics.unwrap_or(usize::max_value()))
+			.map(|pending| pending.original.clone())
+			.collect();
+
+		let block = self.propose_with(extrinsics)?;
+		let good = self.client.evaluate_block(&self.parent_id, block.clone())?;
+		Ok((block, good))
+	}
+
+	// Bound on the size of the transactions packed into a block. Proposals beyond
+	// `MAX_TRANSACTIONS_SIZE` are invalid regardless of what the runtime reports.
+	fn max_block_length(&self) -> usize {
+		match self.client.max_block_length(&self.parent_id) {
+			Ok(length) => ::std::cmp::min(length, MAX_TRANSACTIONS_SIZE),
+			Err(node_api::Error(node_api::ErrorKind::ApiNotImplemented(_), _)) => MAX_TRANSACTIONS_SIZE,
+			Err(e) => {
+				debug!(target: "bft", "{} Unable to fetch the maximum block length: {}", self.log_context(), e);
+				MAX_TRANSACTIONS_SIZE
+			}
+		}
+	}
+
+	// Identifies this proposer in log lines.
+	fn log_context(&self) -> LogContext {
+		LogContext {
+			parent_number: self.parent_number,
+			parent_hash: self.parent_hash,
+			local_id: self.local_key.public(),
+		}
+	}
 
+	/// Size in bytes of the transactions packed into the most recently proposed block.
+	pub fn last_block_bytes(&self) -> usize {
+		*self.last_block_bytes.lock()
+	}
+
+	/// Transaction counts of the most recently proposed block.
+	pub fn last_proposal_counts(&self) -> ProposalCounts {
+		*self.last_counts.lock()
+	}
+}
+
+impl<C, S> bft::Proposer<Block> for Proposer<C, S>
+	where
+		C: Api + Send + Sync + 'static,
+		S: Signer,
+{
+	type Create = Result<Block, Error>;
+	type Error = Error;
+	type Evaluate = Box<Future<Item=EvaluationOutcome, Error=Error>>;
+
+	fn propose(&self) -> Result<Block, Error> {
+		use node_api::BlockBuilder;
+		use runtime_primitives::traits::{Hash as HashT, BlakeTwo256};
+
+		// packing culls the pool, which must not happen twice at once for a parent.
+		let _proposing = ProposingGuard::acquire(&self.proposing, self.parent_hash)?;
+
+		let inherent_data = self.inherent_data()?;
+		let timestamp = inherent_data.timestamp;
 		let mut block_builder = self.client.build_block(&self.parent_id, inherent_data)?;
+		let max_length = self.max_block_length();
 
-		{
+		let (pending_size, included) = {
 			let mut unqueue_invalid = Vec::new();
-			let result = self.transaction_pool.cull_and_get_pending(&BlockId::hash(self.parent_hash), |pending_iterator| {
+			let result = self.transaction_pool.cull_and_get_pending(&self.parent_id, |pending_iterator| {
+				let pending_iterator = self.inclusion_mode.order(pending_iterator, |pending| pending.verified.sender);
+
 				let mut pending_size = 0;
+				let mut included = 0;
+				// senders with a transaction left out; their later ones can't apply either.
+				let mut stalled = HashSet::new();
 				for pending in pending_iterator {
-					if pending_size + pending.verified.encoded_size() >= MAX_TRANSACTIONS_SIZE { break }
+					if self.max_extrinsics.map_or(false, |max| included >= max) { break }
 
-					match block_builder.push_extrinsic(pending.original.clone()) {
+					let encoded_size = pending.verified.encoded_size();
+					let remaining_capacity = max_length - pending_size;
+					if encoded_size >= remaining_capacity { break }
+
+					if stalled.contains(&pending.verified.sender) {
+						trace!(target: "transaction-pool", "Skipping transaction {} after a gap in its sender's indices", pending.verified.hash());
+						continue;
+					}
+
+					if let Some(ref policy) = self.inclusion_policy {
+						if !policy.include(&pending.original, encoded_size, remaining_capacity) {
+							trace!(target: "transaction-pool", "Inclusion policy vetoed transaction {}", pending.verified.hash());
+							stalled.insert(pending.verified.sender);
+							continue;
+						}
+					}
+
+					match self.client.would_apply(&block_builder, &pending.original) {
+						Ok(true) => {}
+						Ok(false) => {
+							trace!(target: "transaction-pool", "Skipping inapplicable transaction {}", pending.verified.hash());
+							unqueue_invalid.push(pending.verified.hash().clone());
+							stalled.insert(pending.verified.sender);
+							continue;
+						}
+						Err(e) => debug!(target: "transaction-pool", "Unable to check transaction {}: {}", pending.verified.hash(), e),
+					}
+
+					match push_extrinsic(&mut block_builder, *pending.verified.hash(), pending.original.clone()) {
 						Ok(()) => {
-							pending_size += pending.verified.encoded_size();
+							pending_size += encoded_size;
+							included += 1;
 						}
 						Err(e) => {
-							trace!(target: "transaction-pool", "Invalid transaction: {}", e);
+							trace!(target: "transaction-pool", "{}", e);
 							unqueue_invalid.push(pending.verified.hash().clone());
+							stalled.insert(pending.verified.sender);
 						}
 					}
 				}
+				(pending_size, included)
 			});
-			if let Err(e) = result {
+
+			self.transaction_pool.remove_with_reason(&unqueue_invalid, RemovalReason::InvalidDuringProposal);
+			result.unwrap_or_else(|e| {
 				warn!("Unable to get the pending set: {:?}", e);
-			}
+				(0, 0)
+			})
+		};
+		let counts = ProposalCounts {
+			pending_total: self.transaction_pool.light_status().transaction_count,
+			included,
+		};
 
-			self.transaction_pool.remove(&unqueue_invalid, false);
+		if let Some(interval) = self.min_empty_interval {
+			let since_last_proposal = self.last_proposal.lock().elapsed();
+			if pending_size == 0 && since_last_proposal < interval {
+				debug!(target: "bft", "{} Not proposing an empty block {:?} after the previous proposal", self.log_context(), since_last_proposal);
+				bail!(ErrorKind::EmptyBlockSuppressed);
+			}
 		}
 
 		let block = block_builder.bake()?;
+		// the interval runs from the last block carrying transactions.
+		if pending_size > 0 {
+			*self.last_proposal.lock() = Instant::now();
+		}
+		*self.last_block_bytes.lock() = pending_size;
+		*self.last_counts.lock() = counts;
 
-		info!("Proposing block [number: {}; hash: {}; parent_hash: {}; extrinsics: [{}]]",
+		info!("{} Proposing block [number: {}; hash: {}; pending_total: {}; included: {}; extrinsics: [{}]]",
+			  self.log_context(),
 			  block.header.number,
 			  Hash::from(block.header.hash()),
-			  block.header.parent_hash,
+			  counts.pending_total,
+			  counts.included,
 			  block.extrinsics.iter()
 			  .map(|xt| format!("{}", BlakeTwo256::hash_of(xt)))
 			  .collect::<Vec<_>>()
@@ -271,25 +772,51 @@ impl<C> bft::Proposer<Block> for Proposer<C>
 		let substrate_block = Decode::decode(&mut block.encode().as_slice())
 			.expect("blocks are defined to serialize to substrate blocks correctly; qed");
 
-		assert!(evaluation::evaluate_initial(
+		// a block we built ourselves failing these is a bug in building, not something to vote on.
+		if let Err(e) = evaluation::evaluate_initial(
 			&substrate_block,
 			timestamp,
+			self.max_vote_delay.as_secs(),
+			self.skew_tolerance,
 			&self.parent_hash,
 			self.parent_number,
-		).is_ok());
+		) {
+			warn!("{} Built a structurally invalid block: {}", self.log_context(), e);
+			bail!(ErrorKind::BadBake(e.to_string()));
+		}
+
+		// informational only, so failing to get the reward doesn't fail the proposal.
+		match self.client.author_reward(&self.parent_id) {
+			Ok(reward) => {
+				info!("{} Expecting an author reward of {}", self.log_context(), reward);
+				if let Some(ref observer) = self.round_observer {
+					observer.on_author_reward(self.parent_number + 1, reward);
+				}
+			}
+			Err(node_api::Error(node_api::ErrorKind::ApiNotImplemented(_), _)) => {}
+			Err(e) => debug!(target: "bft", "{} Unable to get the author reward: {}", self.log_context(), e),
+		}
 
 		Ok(substrate_block)
 	}
 
 	fn evaluate(&self, unchecked_proposal: &Block) -> Self::Evaluate {
-		debug!(target: "bft", "evaluating block on top of parent ({}, {:?})", self.parent_number, self.parent_hash);
+		use runtime_primitives::traits::{Hash as HashT, BlakeTwo256};
 
-		let current_timestamp = current_timestamp();
+		let context = self.log_context();
+		debug!(target: "bft", "{} Evaluating proposal {}", context, Hash::from(unchecked_proposal.header.hash()));
+
+		let current_timestamp = match current_timestamp_checked() {
+			Ok(timestamp) => timestamp,
+			Err(e) => return Box::new(future::err(e)),
+		};
 
 		// do initial serialization and structural integrity checks.
 		let maybe_proposal = evaluation::evaluate_initial(
 			unchecked_proposal,
 			current_timestamp,
+			self.max_vote_delay.as_secs(),
+			self.skew_tolerance,
 			&self.parent_hash,
 			self.parent_number,
 		);
@@ -298,19 +825,32 @@ impl<C> bft::Proposer<Block> for Proposer<C>
 			Ok(p) => p,
 			Err(e) => {
 				// TODO: these errors are easily re-checked in runtime.
-				debug!(target: "bft", "Invalid proposal: {:?}", e);
-				return Box::new(future::ok(false));
+				debug!(target: "bft", "{} Invalid proposal: {:?}", context, e);
+				return Box::new(future::ok(EvaluationOutcome::Invalid));
 			}
 		};
 
+		// cheaply reject proposals whose header doesn't commit to their extrinsics.
+		if self.client.expected_extrinsics_root(unchecked_proposal) != unchecked_proposal.header.extrinsics_root {
+			debug!(target: "bft", "{} Proposal extrinsics don't match the extrinsics root", context);
+			return Box::new(future::ok(EvaluationOutcome::Invalid));
+		}
+
+		// an extrinsic can't be applied twice, so don't bother executing such a block.
+		let mut seen = HashSet::new();
+		if !unchecked_proposal.extrinsics.iter().all(|xt| seen.insert(BlakeTwo256::hash_of(xt))) {
+			debug!(target: "bft", "{} Proposal includes a duplicate extrinsic", context);
+			return Box::new(future::ok(EvaluationOutcome::Invalid));
+		}
+
 		let vote_delays = {
 			let now = Instant::now();
 
 			// the duration until the given timestamp is current
 			let proposed_timestamp = ::std::cmp::max(self.minimum_timestamp, proposal.timestamp());
 			let timestamp_delay = if proposed_timestamp > current_timestamp {
-				let delay_s = proposed_timestamp - current_timestamp;
-				debug!(target: "bft", "Delaying evaluation of proposal for {} seconds", delay_s);
+				let delay_s = ::std::cmp::min(proposed_timestamp - current_timestamp, self.max_vote_delay.as_secs());
+				debug!(target: "bft", "{} Delaying evaluation of proposal for {} seconds", context, delay_s);
 				Some(now + Duration::from_secs(delay_s))
 			} else {
 				None
@@ -324,20 +864,111 @@ impl<C> bft::Proposer<Block> for Proposer<C>
 			}
 		};
 
+		// decode the inherent data the proposal was built from once and bound it. the
+		// timestamp was bounded above.
+		let inherent_data = match self.client.extract_inherents(unchecked_proposal) {
+			Ok(inherent_data) => inherent_data,
+			Err(e) => {
+				debug!(target: "bft", "{} Unable to extract proposal inherents: {}", context, e);
+				return Box::new(future::ok(EvaluationOutcome::Invalid));
+			}
+		};
+		if inherent_data.offline_indices.iter().any(|&i| i as usize >= self.validators.len()) {
+			debug!(target: "bft", "{} Proposal reports unknown validators offline", context);
+			return Box::new(future::ok(EvaluationOutcome::Invalid));
+		}
+
 		// refuse to vote if this block says a validator is offline that we
 		// think isn't.
-		let offline = proposal.noted_offline();
-		if !self.offline.read().check_consistency(&self.validators[..], offline) {
-			return Box::new(futures::empty());
+		let consistent = if self.report_offline {
+			self.offline.with(|tracker| tracker.check_consistency(&self.validators[..], &inherent_data.offline_indices))
+		} else {
+			Ok(())
+		};
+		if let Err(reason) = consistent {
+			let reason = format!("inconsistent offline report: {}", reason);
+			return Box::new(future::ok(EvaluationOutcome::Abstain(reason)));
+		}
+
+		// refuse to vote if the block carries inherents other than the ones the
+		// inherent data produces, or unsigned extrinsics after them.
+		match self.client.check_inherents(&self.parent_id, unchecked_proposal, &inherent_data) {
+			Ok(true) => {}
+			Ok(false) => {
+				debug!(target: "bft", "{} Proposal inherents don't match the inherent data", context);
+				return Box::new(future::ok(EvaluationOutcome::Invalid));
+			}
+			// nothing to compare against; executing the block still checks its inherents.
+			Err(node_api::Error(node_api::ErrorKind::ApiNotImplemented(_), _)) => {
+				debug!(target: "bft", "{} Runtime produces no inherents; leaving them to block execution", context);
+			}
+			Err(e) => return Box::new(future::err(Error::from(e))),
+		}
+		if let Some(ref validator) = self.inherent_validator {
+			if let Err(reason) = validator.validate_inherents(&self.parent_id, &inherent_data) {
+				debug!(target: "bft", "{} Proposal inherents rejected: {}", context, reason);
+				return Box::new(future::ok(EvaluationOutcome::Invalid));
+			}
 		}
 
-		// evaluate whether the block is actually valid.
+		// evaluate whether the block is actually valid, giving up on it if that
+		// takes too long. proposals are immutable, so a previous result can be reused.
 		// TODO: is it better to delay this until the delays are finished?
-		let evaluated = self.client
-			.evaluate_block(&self.parent_id, unchecked_proposal.clone())
-			.map_err(Into::into);
+		let proposal_hash = Hash::from(unchecked_proposal.header.hash());
+		let cached = self.evaluated.lock().get(&proposal_hash).cloned();
+		let evaluated = if let Some(good) = cached {
+			trace!(target: "bft", "{} Reusing evaluation of proposal {}", context, proposal_hash);
+			future::Either::A(future::ok(good))
+		} else {
+			let (tx, rx) = oneshot::channel();
+			let cache = self.evaluated.clone();
+			let client = self.client.clone();
+			let parent_id = self.parent_id.clone();
+			let proposal = unchecked_proposal.clone();
+			let retries = self.evaluate_retries;
+			self.evaluation_limit.execute(move || {
+				let mut attempt = 0;
+				let evaluated = loop {
+					// nobody waits for the result anymore, e.g. past the deadline.
+					if tx.is_canceled() {
+						trace!(target: "bft", "{} Dropping abandoned evaluation of proposal {}", context, proposal_hash);
+						return;
+					}
+
+					let evaluated = client.evaluate_block(&parent_id, proposal.clone());
+					let retry = match evaluated {
+						Err(ref e) => attempt < retries && is_transient(e),
+						Ok(_) => false,
+					};
+					if !retry { break evaluated }
+
+					attempt += 1;
+					debug!(target: "bft", "{} Retrying block evaluation after transient error (attempt {} of {})", context, attempt, retries);
+				};
+				if let Ok(good) = evaluated {
+					cache.lock().insert(proposal_hash, good);
+				}
+				let _ = tx.send(evaluated);
+			});
+
+			future::Either::B(rx.map_err(|_| Error::from(ErrorKind::PrematureDestruction))
+				.and_then(|evaluated| evaluated.map_err(Error::from)))
+		};
 
-		let future = future::result(evaluated).and_then(move |good| {
+		let deadline = self.evaluate_deadline;
+		let timeout = Delay::new(Instant::now() + deadline)
+			.map_err(|e| Error::from(ErrorKind::Timer(e)));
+
+		let evaluated = evaluated.select2(timeout).then(move |res| match res {
+			Ok(future::Either::A((good, _))) => Ok(good),
+			Ok(future::Either::B(_)) => {
+				warn!(target: "bft", "{} Block evaluation took longer than {:?}; voting against", context, deadline);
+				Ok(false)
+			}
+			Err(future::Either::A((e, _))) | Err(future::Either::B((e, _))) => Err(e),
+		});
+
+		let future = evaluated.and_then(move |good| {
 			let end_result = future::ok(good);
 			if good {
 				// delay a "good" vote.
@@ -346,7 +977,13 @@ impl<C> bft::Proposer<Block> for Proposer<C>
 				// don't delay a "bad" evaluation.
 				future::Either::B(end_result)
 			}
-		});
+		}).map(EvaluationOutcome::from);
+
+		let cancelled = self.cancellation.exit.clone()
+			.then(|_| Err(Error::from(ErrorKind::EvaluationCancelled)));
+		let future = future.select(cancelled)
+			.map(|(outcome, _)| outcome)
+			.map_err(|(e, _)| e);
 
 		Box::new(future) as Box<_>
 	}
@@ -354,20 +991,26 @@ impl<C> bft::Proposer<Block> for Proposer<C>
 	fn round_proposer(&self, round_number: usize, authorities: &[AuthorityId]) -> AuthorityId {
 		let offset = self.primary_index(round_number, authorities.len());
 		let proposer = authorities[offset].clone();
-		trace!(target: "bft", "proposer for round {} is {}", round_number, proposer);
+		trace!(target: "bft", "{} Proposer for round {} is {}", self.log_context(), round_number, proposer);
 
 		proposer
 	}
 
+	fn is_round_skipped(error: &Error) -> bool {
+		match *error.kind() {
+			ErrorKind::EmptyBlockSuppressed => true,
+			_ => false,
+		}
+	}
+
 	fn import_misbehavior(&self, misbehavior: Vec<(AuthorityId, bft::Misbehavior<Hash>)>) {
 		use rhododendron::Misbehavior as GenericMisbehavior;
 		use runtime_primitives::bft::{MisbehaviorKind, MisbehaviorReport};
-		use node_primitives::UncheckedExtrinsic as GenericExtrinsic;
 		use node_runtime::{Call, UncheckedExtrinsic, ConsensusCall};
 
-		let local_id = self.local_key.public().0.into();
+		let local_id = self.local_key.public();
 		let mut next_index = {
-			let cur_index = self.transaction_pool.cull_and_get_pending(&BlockId::hash(self.parent_hash), |pending| pending
+			let cur_index = self.transaction_pool.cull_and_get_pending(&self.parent_id, |pending| pending
 				.filter(|tx| tx.verified.sender == local_id)
 				.last()
 				.map(|tx| Ok(tx.verified.index()))
@@ -401,18 +1044,37 @@ impl<C> bft::Proposer<Block> for Proposer<C>
 						=> MisbehaviorKind::BftDoubleCommit(round as u32, (h1, s1.signature), (h2, s2.signature)),
 				}
 			};
+			let encoded_report = report.encode();
+			if self.reported_misbehavior.lock().contains(&encoded_report) {
+				trace!(target: "consensus", "Misbehavior of {} already reported", target);
+				continue;
+			}
+
 			let payload = (next_index, Call::Consensus(ConsensusCall::report_misbehavior(report)), Era::immortal(), self.client.genesis_hash());
-			let signature = self.local_key.sign(&payload.encode()).into();
-			next_index += 1;
+			let signature = self.local_key.sign(&payload.encode());
 
-			let local_id = self.local_key.public().0.into();
 			let extrinsic = UncheckedExtrinsic {
 				signature: Some((node_runtime::RawAddress::Id(local_id), signature, payload.0, Era::immortal())),
 				function: payload.1,
 			};
-			let uxt: GenericExtrinsic = Decode::decode(&mut extrinsic.encode().as_slice()).expect("Encoded extrinsic is valid");
-			self.transaction_pool.submit_one(&BlockId::hash(self.parent_hash), uxt)
-				.expect("locally signed extrinsic is valid; qed");
+			let uxt = match decode_extrinsic(&extrinsic.encode()) {
+				Ok(uxt) => uxt,
+				Err(e) => {
+					warn!(target: "consensus", "Skipping misbehavior report against {}: {}", target, e);
+					continue;
+				}
+			};
+			match self.transaction_pool.submit_one(&self.parent_id, uxt) {
+				Ok(_) => {
+					next_index += 1;
+					self.reported_misbehavior.lock().insert(encoded_report);
+				}
+				Err(transaction_pool::Error(transaction_pool::ErrorKind::PoolFull(_), _)) => {
+					warn!(target: "consensus", "Transaction pool full; dropping remaining misbehavior reports");
+					break;
+				}
+				Err(e) => warn!(target: "consensus", "Unable to submit misbehavior report against {}: {}", target, e),
+			}
 		}
 	}
 
@@ -427,18 +1089,1585 @@ impl<C> bft::Proposer<Block> for Proposer<C>
 		if !was_proposed {
 			let public = ed25519::Public::from_raw(primary_validator.0);
 			info!(
-				"Potential Offline Validator: {} failed to propose during assigned slot: {}",
+				"{} Potential Offline Validator: {} failed to propose during assigned slot: {}",
+				self.log_context(),
 				public,
 				round_number,
 			);
 		}
 
-		self.offline.write().note_round_end(primary_validator, was_proposed);
+		self.offline.with_mut(|offline| offline.note_round_end(primary_validator, was_proposed));
+		if let Some(ref observer) = self.round_observer {
+			observer.on_round_end(round_number, was_proposed, primary_validator);
+		}
+	}
+}
+
+// Marks a parent as being proposed on until dropped.
+struct ProposingGuard<'a> {
+	proposing: &'a Mutex<HashSet<Hash>>,
+	parent_hash: Hash,
+}
+
+impl<'a> ProposingGuard<'a> {
+	fn acquire(proposing: &'a Mutex<HashSet<Hash>>, parent_hash: Hash) -> Result<Self, Error> {
+		if !proposing.lock().insert(parent_hash) {
+			bail!(ErrorKind::ConcurrentProposal(parent_hash));
+		}
+
+		Ok(ProposingGuard { proposing, parent_hash })
+	}
+}
+
+impl<'a> Drop for ProposingGuard<'a> {
+	fn drop(&mut self) {
+		self.proposing.lock().remove(&self.parent_hash);
+	}
+}
+
+// Aborts the evaluations of a proposer once fired.
+struct Cancellation {
+	signal: Mutex<Option<::exit_future::Signal>>,
+	exit: ::exit_future::Exit,
+}
+
+impl Default for Cancellation {
+	fn default() -> Self {
+		let (signal, exit) = ::exit_future::signal();
+		Cancellation {
+			signal: Mutex::new(Some(signal)),
+			exit,
+		}
+	}
+}
+
+// Prefix shared by the log lines of a proposer, so they can be correlated.
+#[derive(Clone, Copy)]
+struct LogContext {
+	parent_number: BlockNumber,
+	parent_hash: Hash,
+	local_id: AccountId,
+}
+
+impl fmt::Display for LogContext {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "[parent: #{} ({}); local: {}]", self.parent_number, self.parent_hash, self.local_id)
+	}
+}
+
+// Backend errors (e.g. a momentarily locked database) may go away if retried.
+fn is_transient(err: &node_api::Error) -> bool {
+	match *err.kind() {
+		node_api::ErrorKind::Backend(_) | node_api::ErrorKind::Blockchain(_) => true,
+		_ => false,
+	}
+}
+
+// Push an extrinsic onto the block, attributing failure to the extrinsic's hash.
+fn push_extrinsic<B: node_api::BlockBuilder>(builder: &mut B, hash: Hash, extrinsic: UncheckedExtrinsic) -> Result<(), Error> {
+	builder.push_extrinsic(extrinsic).map_err(|e| ErrorKind::InvalidExtrinsic(hash, e.to_string()).into())
+}
+
+// Interleave items across keys, taking one item per key in turn while keeping
+// the relative order of items sharing a key.
+fn interleave_by<I, K, F>(items: I, key: F) -> Vec<I::Item>
+	where
+		I: Iterator,
+		K: Eq + ::std::hash::Hash,
+		F: Fn(&I::Item) -> K,
+{
+	let mut queues: Vec<VecDeque<I::Item>> = Vec::new();
+	let mut positions = HashMap::new();
+	let mut total = 0;
+	for item in items {
+		let position = *positions.entry(key(&item)).or_insert_with(|| {
+			queues.push(VecDeque::new());
+			queues.len() - 1
+		});
+		queues[position].push_back(item);
+		total += 1;
+	}
+
+	let mut interleaved = Vec::with_capacity(total);
+	while interleaved.len() < total {
+		for queue in &mut queues {
+			interleaved.extend(queue.pop_front());
+		}
 	}
+	interleaved
 }
 
+// Re-interpret a runtime extrinsic as the generic node extrinsic the pool accepts.
+fn decode_extrinsic(encoded: &[u8]) -> Result<node_primitives::UncheckedExtrinsic, Error> {
+	Decode::decode(&mut &encoded[..]).ok_or_else(|| ErrorKind::MisbehaviorEncoding.into())
+}
+
+#[cfg(test)]
 fn current_timestamp() -> Timestamp {
-	time::SystemTime::now().duration_since(time::UNIX_EPOCH)
-		.expect("now always later than unix epoch; qed")
-		.as_secs()
+	current_timestamp_checked().expect("now always later than unix epoch; qed")
+}
+
+// The current timestamp, failing on a clock set before the unix epoch.
+fn current_timestamp_checked() -> Result<Timestamp, Error> {
+	timestamp_at(time::SystemTime::now())
+}
+
+fn timestamp_at(now: time::SystemTime) -> Result<Timestamp, Error> {
+	now.duration_since(time::UNIX_EPOCH)
+		.map(|since_epoch| since_epoch.as_secs())
+		.map_err(|e| ErrorKind::ClockUnavailable(e).into())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use node_api::BlockBuilder;
+	use node_primitives::{Balance, Index, UncheckedExtrinsic};
+	use node_runtime::Address;
+	use runtime_primitives::traits::{CurrentHeight, BlockNumberToHash};
+	use runtime_primitives::transaction_validity::TransactionValidity;
+	use runtime_primitives::bft::{MisbehaviorKind, MisbehaviorReport};
+	use substrate_keyring::Keyring;
+
+	const GENESIS_HASH: [u8; 32] = [1; 32];
+
+	struct NullSink;
+
+	impl Sink for NullSink {
+		type SinkItem = bft::Communication<Block>;
+		type SinkError = Error;
+
+		fn start_send(&mut self, _item: Self::SinkItem) -> StartSend<Self::SinkItem, Error> {
+			Ok(AsyncSink::Ready)
+		}
+
+		fn poll_complete(&mut self) -> Poll<(), Error> {
+			Ok(Async::Ready(()))
+		}
+	}
+
+	struct NoNetwork;
+
+	impl Network for NoNetwork {
+		type Input = ::futures::stream::Empty<bft::Communication<Block>, Error>;
+		type Output = NullSink;
+
+		fn communication_for(
+			&self,
+			_validators: &[SessionKey],
+			_local_id: SessionKey,
+			_parent_hash: Hash,
+			_task_executor: TaskExecutor,
+		) -> (Self::Input, Self::Output) {
+			(::futures::stream::empty(), NullSink)
+		}
+	}
+
+	// Remembers the local id it was asked to communicate as.
+	#[derive(Default)]
+	struct LocalIdNetwork(Mutex<Option<SessionKey>>);
+
+	impl<'a> Network for &'a LocalIdNetwork {
+		type Input = ::futures::stream::Empty<bft::Communication<Block>, Error>;
+		type Output = NullSink;
+
+		fn communication_for(
+			&self,
+			_validators: &[SessionKey],
+			local_id: SessionKey,
+			_parent_hash: Hash,
+			_task_executor: TaskExecutor,
+		) -> (Self::Input, Self::Output) {
+			*self.0.lock() = Some(local_id);
+			(::futures::stream::empty(), NullSink)
+		}
+	}
+
+	struct TestBlockBuilder {
+		parent_hash: Hash,
+		number: BlockNumber,
+		extrinsics: Vec<UncheckedExtrinsic>,
+		inapplicable: Vec<UncheckedExtrinsic>,
+	}
+
+	impl BlockBuilder for TestBlockBuilder {
+		fn push_extrinsic(&mut self, extrinsic: UncheckedExtrinsic) -> node_api::Result<()> {
+			assert!(!self.inapplicable.contains(&extrinsic), "inapplicable extrinsic pushed");
+			self.extrinsics.push(extrinsic);
+			Ok(())
+		}
+
+		fn bake(self) -> node_api::Result<Block> {
+			use runtime_primitives::traits::{Hash as HashT, BlakeTwo256};
+
+			Ok(Block {
+				header: Header {
+					parent_hash: self.parent_hash,
+					number: self.number,
+					state_root: Default::default(),
+					extrinsics_root: BlakeTwo256::ordered_trie_root(self.extrinsics.iter().map(Encode::encode)),
+					digest: Default::default(),
+				},
+				extrinsics: self.extrinsics,
+			})
+		}
+	}
+
+	#[derive(Default)]
+	struct TestApi {
+		validators: Vec<AccountId>,
+		session_keys: Option<Vec<SessionKey>>,
+		known_addresses: bool,
+		no_random_seed: bool,
+		inapplicable: Vec<UncheckedExtrinsic>,
+		apply_checks: Mutex<usize>,
+		max_block_length: Mutex<Option<usize>>,
+		author_reward: Option<Balance>,
+		misnumbered_blocks: bool,
+		unknown_blocks: bool,
+		evaluate_delay: Option<Duration>,
+		bad_blocks: bool,
+		transient_failures: Mutex<usize>,
+		evaluations: Mutex<usize>,
+		// evaluations executing now and the most ever executing at once.
+		evaluations_in_flight: Mutex<(usize, usize)>,
+		combined_context: bool,
+		// the runtime produces no inherent extrinsics to check proposals against.
+		no_inherent_api: bool,
+		context_calls: Mutex<usize>,
+		session_index: Mutex<BlockNumber>,
+		no_session_index: bool,
+		validator_fetches: Mutex<usize>,
+	}
+
+	impl CurrentHeight for TestApi {
+		type BlockNumber = BlockNumber;
+
+		fn current_height(&self) -> BlockNumber {
+			1
+		}
+	}
+
+	impl BlockNumberToHash for TestApi {
+		type BlockNumber = BlockNumber;
+		type Hash = Hash;
+
+		fn block_number_to_hash(&self, n: BlockNumber) -> Option<Hash> {
+			match n {
+				0 => Some(GENESIS_HASH.into()),
+				_ => None,
+			}
+		}
+	}
+
+	impl Api for TestApi {
+		type BlockBuilder = TestBlockBuilder;
+
+		fn genesis_block_hash(&self) -> node_api::Result<Hash> {
+			Ok(GENESIS_HASH.into())
+		}
+
+		fn chain_spec_name(&self, _at: &BlockId) -> node_api::Result<String> {
+			Ok("test-node".into())
+		}
+
+		fn has_block(&self, _at: &BlockId) -> node_api::Result<bool> {
+			Ok(!self.unknown_blocks)
+		}
+
+		fn session_keys(&self, _at: &BlockId) -> node_api::Result<Vec<SessionKey>> {
+			if let Some(ref keys) = self.session_keys {
+				return Ok(keys.clone());
+			}
+			Ok(self.validators.iter().map(|v| v.0.into()).collect())
+		}
+
+		fn validators(&self, _at: &BlockId) -> node_api::Result<Vec<AccountId>> {
+			*self.validator_fetches.lock() += 1;
+			Ok(self.validators.clone())
+		}
+
+		fn session_index(&self, _at: &BlockId) -> node_api::Result<BlockNumber> {
+			if self.no_session_index {
+				return Err(node_api::ErrorKind::ApiNotImplemented("session_index").into());
+			}
+			Ok(*self.session_index.lock())
+		}
+
+		fn random_seed(&self, _at: &BlockId) -> node_api::Result<Hash> {
+			if self.no_random_seed {
+				return Err(node_api::ErrorKind::ApiNotImplemented("random_seed").into());
+			}
+			Ok([7; 32].into())
+		}
+
+		fn consensus_context(&self, at: &BlockId) -> node_api::Result<(Hash, Vec<AccountId>)> {
+			if !self.combined_context {
+				return Ok((self.random_seed(at)?, self.validators(at)?));
+			}
+
+			*self.context_calls.lock() += 1;
+			Ok(([7; 32].into(), self.validators.clone()))
+		}
+
+		fn max_block_length(&self, _at: &BlockId) -> node_api::Result<usize> {
+			self.max_block_length.lock().ok_or_else(|| node_api::ErrorKind::ApiNotImplemented("max_block_length").into())
+		}
+
+		fn author_reward(&self, _at: &BlockId) -> node_api::Result<Balance> {
+			self.author_reward.ok_or_else(|| node_api::ErrorKind::ApiNotImplemented("author_reward").into())
+		}
+
+		fn timestamp(&self, _at: &BlockId) -> node_api::Result<Timestamp> {
+			Ok(current_timestamp())
+		}
+
+		fn index(&self, _at: &BlockId, _account: AccountId) -> node_api::Result<Index> {
+			Ok(0)
+		}
+
+		fn balance(&self, _at: &BlockId, _account: AccountId) -> node_api::Result<Balance> {
+			Ok(0)
+		}
+
+		fn stake_of(&self, _at: &BlockId, _account: AccountId) -> node_api::Result<Balance> {
+			Ok(0)
+		}
+
+		fn lookup(&self, _at: &BlockId, address: Address) -> node_api::Result<Option<AccountId>> {
+			match address {
+				node_runtime::RawAddress::Id(id) if self.known_addresses => Ok(Some(id)),
+				_ => Ok(None),
+			}
+		}
+
+		fn validate_transaction(&self, _at: &BlockId, _tx: UncheckedExtrinsic) -> node_api::Result<TransactionValidity> {
+			Ok(TransactionValidity::Unknown)
+		}
+
+		fn evaluate_block(&self, _at: &BlockId, _block: Block) -> node_api::Result<bool> {
+			*self.evaluations.lock() += 1;
+			{
+				let mut in_flight = self.evaluations_in_flight.lock();
+				in_flight.0 += 1;
+				in_flight.1 = ::std::cmp::max(in_flight.0, in_flight.1);
+			}
+			if let Some(delay) = self.evaluate_delay {
+				thread::sleep(delay);
+			}
+			self.evaluations_in_flight.lock().0 -= 1;
+
+			if self.bad_blocks {
+				return Ok(false);
+			}
+
+			let mut transient_failures = self.transient_failures.lock();
+			if *transient_failures > 0 {
+				*transient_failures -= 1;
+				return Err(node_api::ErrorKind::Backend("database is locked".into()).into());
+			}
+			Ok(true)
+		}
+
+		fn build_block(&self, at: &BlockId, inherent_data: InherentData) -> node_api::Result<TestBlockBuilder> {
+			let parent_hash = match *at {
+				BlockId::Hash(h) => h,
+				BlockId::Number(_) => Default::default(),
+			};
+
+			Ok(TestBlockBuilder {
+				parent_hash,
+				number: if self.misnumbered_blocks { 2 } else { 1 },
+				extrinsics: self.inherent_extrinsics(at, inherent_data)?,
+				inapplicable: self.inapplicable.clone(),
+			})
+		}
+
+		fn would_apply(&self, builder: &TestBlockBuilder, extrinsic: &UncheckedExtrinsic) -> node_api::Result<bool> {
+			*self.apply_checks.lock() += 1;
+			Ok(!builder.inapplicable.contains(extrinsic))
+		}
+
+		fn inherent_extrinsics(&self, _at: &BlockId, inherent_data: InherentData) -> node_api::Result<Vec<UncheckedExtrinsic>> {
+			use node_runtime::{Call, ConsensusCall, TimestampCall, UncheckedExtrinsic as RuntimeExtrinsic};
+			use runtime_primitives::generic::UncheckedMortalExtrinsic;
+
+			if self.no_inherent_api {
+				return Err(node_api::ErrorKind::ApiNotImplemented("inherent_extrinsics").into());
+			}
+
+			let mut inherent: Vec<RuntimeExtrinsic> = vec![UncheckedMortalExtrinsic::new_unsigned(
+				Call::Timestamp(TimestampCall::set(inherent_data.timestamp))
+			)];
+
+			if !inherent_data.offline_indices.is_empty() {
+				inherent.push(UncheckedMortalExtrinsic::new_unsigned(
+					Call::Consensus(ConsensusCall::note_offline(inherent_data.offline_indices))
+				));
+			}
+
+			Ok(inherent.into_iter()
+				.map(|xt| Decode::decode(&mut xt.encode().as_slice()).expect("runtime extrinsics are valid node extrinsics; qed"))
+				.collect())
+		}
+	}
+
+	fn builder_for(proposer: &Proposer<TestApi>) -> TestBlockBuilder {
+		TestBlockBuilder {
+			parent_hash: proposer.parent_hash,
+			number: proposer.parent_number + 1,
+			extrinsics: Vec::new(),
+			inapplicable: Vec::new(),
+		}
+	}
+
+	fn signed_extrinsic(who: Keyring, index: Index) -> UncheckedExtrinsic {
+		use node_runtime::{Call, TimestampCall, UncheckedExtrinsic as RuntimeExtrinsic};
+
+		let function = Call::Timestamp(TimestampCall::set(index));
+		let payload = (index, function, Era::immortal(), Hash::from(GENESIS_HASH));
+		let signature = who.sign(&payload.encode()).into();
+		let extrinsic = RuntimeExtrinsic {
+			signature: Some((node_runtime::RawAddress::Id(who.to_raw_public().into()), signature, payload.0, Era::immortal())),
+			function: payload.1,
+		};
+
+		Decode::decode(&mut extrinsic.encode().as_slice()).expect("runtime extrinsics are valid node extrinsics; qed")
+	}
+
+	#[derive(Default)]
+	struct RecordingSigner {
+		signed: Mutex<Vec<Vec<u8>>>,
+	}
+
+	impl Signer for RecordingSigner {
+		fn public(&self) -> AccountId {
+			Keyring::Alice.to_raw_public().into()
+		}
+
+		fn sign(&self, message: &[u8]) -> Signature {
+			self.signed.lock().push(message.to_vec());
+			Default::default()
+		}
+	}
+
+	fn proposer(api: TestApi) -> Proposer<TestApi> {
+		proposer_with_signer(api, Keyring::Alice.pair())
+	}
+
+	fn proposer_with_signer<S: Signer>(api: TestApi, signer: S) -> Proposer<TestApi, S> {
+		let api = Arc::new(api);
+		let parent_hash: Hash = GENESIS_HASH.into();
+
+		Proposer {
+			client: api.clone(),
+			start: Instant::now(),
+			local_key: Arc::new(signer),
+			parent_hash,
+			parent_id: BlockId::hash(parent_hash),
+			parent_number: 0,
+			random_seed: Default::default(),
+			transaction_pool: Arc::new(TransactionPool::new(Default::default(), transaction_pool::ChainApi::new(api.clone()))),
+			offline: SharedOfflineTracker::new(OfflineTracker::new()),
+			report_offline: true,
+			max_offline_reports: DEFAULT_MAX_OFFLINE_REPORTS,
+			min_empty_interval: None,
+			last_proposal: Arc::new(Mutex::new(Instant::now())),
+			proposing: Default::default(),
+			evaluate_deadline: DEFAULT_EVALUATE_DEADLINE,
+			evaluate_retries: DEFAULT_EVALUATE_RETRIES,
+			evaluation_limit: Default::default(),
+			max_vote_delay: DEFAULT_MAX_VOTE_DELAY,
+			skew_tolerance: DEFAULT_SKEW_TOLERANCE,
+			inherent_provider: None,
+			inherent_validator: None,
+			inclusion_policy: None,
+			inclusion_mode: InclusionMode::Greedy,
+			max_extrinsics: None,
+			round_observer: None,
+			evaluated: Default::default(),
+			last_block_bytes: Mutex::new(0),
+			last_counts: Mutex::new(ProposalCounts::default()),
+			reported_misbehavior: Default::default(),
+			cancellation: Default::default(),
+			validators: api.validators.clone(),
+			minimum_timestamp: current_timestamp(),
+		}
+	}
+
+	fn double_prepare() -> bft::Misbehavior<Hash> {
+		use primitives::ed25519::LocalizedSignature;
+		use rhododendron::Misbehavior as GenericMisbehavior;
+
+		let signature = |msg: &[u8]| LocalizedSignature {
+			signer: Keyring::Bob.into(),
+			signature: Keyring::Bob.sign(msg),
+		};
+
+		GenericMisbehavior::DoublePrepare(
+			0,
+			([2; 32].into(), signature(b"first")),
+			([3; 32].into(), signature(b"second")),
+		)
+	}
+
+	#[test]
+	fn rejected_misbehavior_report_does_not_panic() {
+		// the pool can't resolve the local address, so it rejects the report.
+		let proposer = proposer(TestApi::default());
+		let target: AuthorityId = Keyring::Bob.to_raw_public().into();
+
+		bft::Proposer::import_misbehavior(&proposer, vec![(target, double_prepare())]);
+
+		assert_eq!(proposer.transaction_pool.light_status().transaction_count, 0);
+	}
+
+	#[test]
+	fn repeated_misbehavior_is_reported_once() {
+		let proposer = proposer(TestApi { known_addresses: true, ..Default::default() });
+		let target: AuthorityId = Keyring::Bob.to_raw_public().into();
+
+		bft::Proposer::import_misbehavior(&proposer, vec![(target, double_prepare())]);
+		bft::Proposer::import_misbehavior(&proposer, vec![(target, double_prepare())]);
+
+		assert_eq!(proposer.transaction_pool.light_status().transaction_count, 1);
+	}
+
+	#[test]
+	fn misbehavior_reports_are_signed_with_the_configured_signer() {
+		use node_runtime::{Call, ConsensusCall};
+
+		let proposer = proposer_with_signer(TestApi::default(), RecordingSigner::default());
+		let target: AuthorityId = Keyring::Bob.to_raw_public().into();
+
+		bft::Proposer::import_misbehavior(&proposer, vec![(target, double_prepare())]);
+
+		let report = MisbehaviorReport {
+			parent_hash: GENESIS_HASH.into(),
+			parent_number: 0,
+			target,
+			misbehavior: match double_prepare() {
+				::rhododendron::Misbehavior::DoublePrepare(round, (h1, s1), (h2, s2))
+					=> MisbehaviorKind::BftDoublePrepare(round as u32, (h1, s1.signature), (h2, s2.signature)),
+				_ => unreachable!(),
+			},
+		};
+		let payload = (1u64, Call::Consensus(ConsensusCall::report_misbehavior(report)), Era::immortal(), proposer.client.genesis_hash());
+
+		assert_eq!(*proposer.local_key.signed.lock(), vec![payload.encode()]);
+	}
+
+	#[test]
+	fn rejected_push_names_the_extrinsic() {
+		struct RejectingBuilder;
+
+		impl BlockBuilder for RejectingBuilder {
+			fn push_extrinsic(&mut self, _extrinsic: UncheckedExtrinsic) -> node_api::Result<()> {
+				Err(node_api::ErrorKind::Backend("rejected".into()).into())
+			}
+
+			fn bake(self) -> node_api::Result<Block> {
+				unimplemented!()
+			}
+		}
+
+		let hash: Hash = [5; 32].into();
+		match push_extrinsic(&mut RejectingBuilder, hash, signed_extrinsic(Keyring::Alice, 0)) {
+			Err(Error(ErrorKind::InvalidExtrinsic(h, _), _)) => assert_eq!(h, hash),
+			other => panic!("unexpected result: {:?}", other),
+		}
+	}
+
+	#[test]
+	fn corrupt_misbehavior_encoding_is_an_error() {
+		let target: AuthorityId = Keyring::Bob.to_raw_public().into();
+		let report = node_runtime::Call::Consensus(node_runtime::ConsensusCall::report_misbehavior(MisbehaviorReport {
+			parent_hash: GENESIS_HASH.into(),
+			parent_number: 0,
+			target,
+			misbehavior: MisbehaviorKind::BftDoublePrepare(1, ([1; 32].into(), Default::default()), ([2; 32].into(), Default::default())),
+		}));
+		let extrinsic = node_runtime::UncheckedExtrinsic::new_unsigned(report).encode();
+		assert!(decode_extrinsic(&extrinsic).is_ok());
+
+		let truncated = &extrinsic[..extrinsic.len() - 1];
+		match decode_extrinsic(truncated) {
+			Err(Error(ErrorKind::MisbehaviorEncoding, _)) => {},
+			other => panic!("unexpected result: {:?}", other.map(|_| ())),
+		}
+	}
+
+	#[test]
+	fn evaluate_rejects_surplus_inherents() {
+		let validators: Vec<AccountId> = vec![[10; 32].into(), [11; 32].into()];
+		let proposer = proposer(TestApi { validators: validators.clone(), ..Default::default() });
+		proposer.offline.with_mut(|offline| offline.force_offline(validators[0]));
+
+		let timestamp = current_timestamp();
+		let offline = |offline_indices| InherentData { timestamp, offline_indices };
+
+		let mut builder = proposer.client.build_block(&proposer.parent_id, offline(vec![0])).unwrap();
+		let good = builder.extrinsics.clone();
+		let bogus = proposer.client.inherent_extrinsics(&proposer.parent_id, offline(vec![1])).unwrap();
+		builder.push_extrinsic(bogus[1].clone()).unwrap();
+		let block = builder.bake().unwrap();
+
+		assert!(!proposer.client.check_inherents(&proposer.parent_id, &block, &offline(vec![0])).unwrap());
+		assert_eq!(bft::Proposer::evaluate(&proposer, &block).wait().unwrap(), EvaluationOutcome::Invalid);
+
+		let block = TestBlockBuilder { extrinsics: good, ..builder_for(&proposer) }.bake().unwrap();
+		assert!(proposer.client.check_inherents(&proposer.parent_id, &block, &offline(vec![0])).unwrap());
+	}
+
+	#[test]
+	fn inherents_are_extracted_from_proposal() {
+		let proposer = proposer(TestApi::default());
+		let timestamp = current_timestamp();
+		let inherent_data = InherentData { timestamp, offline_indices: vec![0, 2] };
+		let block = proposer.client.build_block(&proposer.parent_id, inherent_data).unwrap().bake().unwrap();
+
+		let extracted = proposer.client.extract_inherents(&block).unwrap();
+
+		assert_eq!(extracted.timestamp, timestamp);
+		assert_eq!(extracted.offline_indices, vec![0, 2]);
+
+		let without_inherents = TestBlockBuilder { extrinsics: Vec::new(), ..builder_for(&proposer) }.bake().unwrap();
+		assert!(proposer.client.extract_inherents(&without_inherents).is_err());
+	}
+
+	#[test]
+	fn inherents_are_left_to_execution_without_inherent_api() {
+		let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+		let checking = proposer(TestApi::default());
+		let inherent_data = |timestamp| InherentData { timestamp, offline_indices: Vec::new() };
+
+		let mut builder = checking.client.build_block(&checking.parent_id, inherent_data(current_timestamp())).unwrap();
+		let surplus = checking.client.inherent_extrinsics(&checking.parent_id, inherent_data(0)).unwrap();
+		builder.push_extrinsic(surplus[0].clone()).unwrap();
+		let block = builder.bake().unwrap();
+		assert_eq!(runtime.block_on(bft::Proposer::evaluate(&checking, &block)).unwrap(), EvaluationOutcome::Invalid);
+
+		let lacking = proposer(TestApi { no_inherent_api: true, ..Default::default() });
+		assert_eq!(runtime.block_on(bft::Proposer::evaluate(&lacking, &block)).unwrap(), EvaluationOutcome::Valid);
+	}
+
+	#[test]
+	fn evaluate_rejects_offline_reports_of_unknown_validators() {
+		let validators: Vec<AccountId> = vec![[10; 32].into(), [11; 32].into()];
+		let proposer = proposer(TestApi { validators, ..Default::default() });
+
+		let inherent_data = InherentData { timestamp: current_timestamp(), offline_indices: vec![5] };
+		let block = proposer.client.build_block(&proposer.parent_id, inherent_data).unwrap().bake().unwrap();
+
+		assert_eq!(bft::Proposer::evaluate(&proposer, &block).wait().unwrap(), EvaluationOutcome::Invalid);
+	}
+
+	#[test]
+	fn evaluate_abstains_on_inconsistent_offline_report() {
+		let validators: Vec<AccountId> = vec![[10; 32].into(), [11; 32].into()];
+		let proposer = proposer(TestApi { validators, ..Default::default() });
+
+		let inherent_data = InherentData { timestamp: current_timestamp(), offline_indices: vec![1] };
+		let block = proposer.client.build_block(&proposer.parent_id, inherent_data).unwrap().bake().unwrap();
+
+		let reason = format!("inconsistent offline report: {}", Inconsistency::ThoughtOnline(1));
+		assert_eq!(bft::Proposer::evaluate(&proposer, &block).wait().unwrap(), EvaluationOutcome::Abstain(reason));
+	}
+
+	fn factory<N: Network>(api: Arc<TestApi>, network: N, handle: TaskExecutor) -> ProposerFactory<N, TestApi> {
+		ProposerFactory {
+			client: api.clone(),
+			transaction_pool: Arc::new(TransactionPool::new(Default::default(), transaction_pool::ChainApi::new(api))),
+			network,
+			handle,
+			offline: SharedOfflineTracker::new(OfflineTracker::new()),
+			report_offline: true,
+			max_offline_reports: DEFAULT_MAX_OFFLINE_REPORTS,
+			min_empty_interval: None,
+			last_proposal: Arc::new(Mutex::new(Instant::now())),
+			proposing: Default::default(),
+			evaluate_deadline: DEFAULT_EVALUATE_DEADLINE,
+			evaluate_retries: DEFAULT_EVALUATE_RETRIES,
+			evaluation_limit: Default::default(),
+			max_vote_delay: DEFAULT_MAX_VOTE_DELAY,
+			skew_tolerance: DEFAULT_SKEW_TOLERANCE,
+			inherent_provider: None,
+			inherent_validator: None,
+			inclusion_policy: None,
+			inclusion_mode: InclusionMode::Greedy,
+			max_extrinsics: None,
+			round_observer: None,
+			validator_cache: Default::default(),
+		}
+	}
+
+	fn genesis_header() -> Header {
+		Header {
+			parent_hash: Default::default(),
+			number: 0,
+			state_root: Default::default(),
+			extrinsics_root: Default::default(),
+			digest: Default::default(),
+		}
+	}
+
+	#[test]
+	fn init_falls_back_to_parent_hash_seed() {
+		use runtime_primitives::traits::{Hash as HashT, BlakeTwo256};
+
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let api = Arc::new(TestApi { no_random_seed: true, ..Default::default() });
+		let factory = factory(api, NoNetwork, runtime.executor());
+		let parent = genesis_header();
+
+		let (proposer, _, _) = bft::Environment::init(&factory, &parent, &[], Arc::new(Keyring::Alice.pair())).unwrap();
+
+		assert_eq!(proposer.random_seed, BlakeTwo256::hash(&*parent.hash()));
+	}
+
+	#[test]
+	fn size_limits_are_shared() {
+		let limits = node_primitives::SizeLimits::default();
+		assert_eq!(limits.max_block_size % limits.max_transaction_size, 0);
+		assert_eq!(MAX_TRANSACTIONS_SIZE, limits.max_block_size);
+	}
+
+	#[test]
+	fn proposer_exposes_its_parent() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let factory = factory(Arc::new(TestApi::default()), NoNetwork, runtime.executor());
+		let parent = Header { number: 7, ..genesis_header() };
+
+		let (proposer, _, _) = bft::Environment::init(&factory, &parent, &[], Arc::new(Keyring::Alice.pair())).unwrap();
+
+		assert_eq!(proposer.parent(), (parent.hash(), 7));
+	}
+
+	#[test]
+	fn clock_before_epoch_is_an_error() {
+		let before_epoch = time::UNIX_EPOCH - Duration::from_secs(1);
+
+		match timestamp_at(before_epoch) {
+			Err(Error(ErrorKind::ClockUnavailable(_), _)) => {}
+			other => panic!("expected an unavailable clock, got {:?}", other),
+		}
+		assert_eq!(timestamp_at(time::UNIX_EPOCH + Duration::from_secs(5)).unwrap(), 5);
+	}
+
+	#[test]
+	fn proposer_exposes_its_validators() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let validators: Vec<AccountId> = vec![[10; 32].into(), [11; 32].into(), [12; 32].into()];
+		let api = Arc::new(TestApi { validators: validators.clone(), ..Default::default() });
+		let factory = factory(api, NoNetwork, runtime.executor());
+
+		let (proposer, _, _) = bft::Environment::init(&factory, &genesis_header(), &[], Arc::new(Keyring::Alice.pair())).unwrap();
+
+		assert_eq!(proposer.validators(), &validators[..]);
+	}
+
+	// Imports whatever is agreed upon under a fixed authority set.
+	struct TestImport {
+		authorities: Vec<AuthorityId>,
+		imported: Mutex<Vec<Block>>,
+	}
+
+	impl bft::BlockImport<Block> for TestImport {
+		fn import_block(&self, block: Block, _justification: bft::Justification<Hash>, _authorities: &[AuthorityId]) -> bool {
+			self.imported.lock().push(block);
+			true
+		}
+	}
+
+	impl bft::Authorities<Block> for TestImport {
+		fn authorities(&self, _at: &BlockId) -> Result<Vec<AuthorityId>, bft::Error> {
+			Ok(self.authorities.clone())
+		}
+	}
+
+	// a chain which never moves past genesis, so the service only starts agreements on its timer.
+	impl client::BlockchainEvents<Block> for TestImport {
+		fn import_notification_stream(&self) -> client::BlockchainEventStream<Block> {
+			::futures::sync::mpsc::unbounded().1
+		}
+
+		fn storage_changes_notification_stream(&self, _filter_keys: Option<&[primitives::storage::StorageKey]>) -> client::error::Result<client::StorageEventStream<Hash>> {
+			unimplemented!()
+		}
+	}
+
+	impl client::ChainHead<Block> for TestImport {
+		fn best_block_header(&self) -> client::error::Result<Header> {
+			Ok(genesis_header())
+		}
+	}
+
+	impl client::BlockBody<Block> for TestImport {
+		fn block_body(&self, _id: &BlockId) -> client::error::Result<Option<Vec<UncheckedExtrinsic>>> {
+			Ok(None)
+		}
+	}
+
+	#[test]
+	fn single_authority_round_over_loopback() {
+		use testing::LoopbackNetwork;
+
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let mut local = tokio::runtime::current_thread::Runtime::new().unwrap();
+		let key = Arc::new(Keyring::Alice.pair());
+		let api = Arc::new(TestApi {
+			validators: vec![Keyring::Alice.to_raw_public().into()],
+			..Default::default()
+		});
+		let client = Arc::new(TestImport {
+			authorities: vec![key.public().into()],
+			imported: Mutex::new(Vec::new()),
+		});
+		let service = bft::BftService::new(client.clone(), key, factory(api, LoopbackNetwork, runtime.executor()));
+
+		let agreement = service.build_upon(&genesis_header()).unwrap().expect("no agreement in progress; qed");
+		// the agreement isn't `Send`, so drive it on the current thread.
+		local.block_on(agreement).unwrap();
+
+		let imported = client.imported.lock();
+		assert_eq!(imported.len(), 1);
+		assert_eq!(imported[0].header.parent_hash, genesis_header().hash());
+	}
+
+	#[test]
+	fn three_authorities_agree_over_bus() {
+		use testing::BusNetwork;
+
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let mut local = tokio::runtime::current_thread::Runtime::new().unwrap();
+		let keyring = [Keyring::Alice, Keyring::Bob, Keyring::Charlie];
+		let api = Arc::new(TestApi {
+			validators: keyring.iter().map(|k| k.to_raw_public().into()).collect(),
+			..Default::default()
+		});
+		let network = BusNetwork::default();
+
+		let clients: Vec<_> = keyring.iter().map(|_| Arc::new(TestImport {
+			authorities: keyring.iter().map(|k| k.pair().public().into()).collect(),
+			imported: Mutex::new(Vec::new()),
+		})).collect();
+		let services: Vec<_> = keyring.iter().zip(&clients).map(|(k, client)| {
+			let factory = factory(api.clone(), network.clone(), runtime.executor());
+			bft::BftService::new(client.clone(), Arc::new(k.pair()), factory)
+		}).collect();
+
+		let agreements: Vec<_> = services.iter()
+			.map(|service| service.build_upon(&genesis_header()).unwrap().expect("no agreement in progress; qed"))
+			.collect();
+		local.block_on(future::join_all(agreements)).unwrap();
+
+		let imported: Vec<_> = clients.iter().map(|client| {
+			let imported = client.imported.lock();
+			assert_eq!(imported.len(), 1);
+			imported[0].header.hash()
+		}).collect();
+		assert!(imported.iter().all(|hash| *hash == imported[0]));
+	}
+
+	#[test]
+	fn service_shutdown_drains_agreement_in_progress() {
+		use testing::LoopbackNetwork;
+
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let api = Arc::new(TestApi {
+			validators: vec![Keyring::Alice.to_raw_public().into()],
+			..Default::default()
+		});
+		let client = Arc::new(TestImport {
+			authorities: vec![Keyring::Alice.pair().public().into()],
+			imported: Mutex::new(Vec::new()),
+		});
+		let transaction_pool = Arc::new(TransactionPool::new(Default::default(), transaction_pool::ChainApi::new(api.clone())));
+		let service = Service::new(
+			client.clone(),
+			api.clone(),
+			LoopbackNetwork,
+			transaction_pool,
+			runtime.executor(),
+			Keyring::Alice.pair(),
+			Duration::from_secs(1),
+		);
+
+		// the first agreement starts on the service's timer.
+		let start = Instant::now();
+		while *api.validator_fetches.lock() == 0 {
+			assert!(start.elapsed() < Duration::from_secs(30), "no agreement was started");
+			thread::sleep(Duration::from_millis(10));
+		}
+
+		let start = Instant::now();
+		service.shutdown().wait().unwrap();
+
+		assert!(start.elapsed() < service::SHUTDOWN_TIMEOUT);
+		assert!(!client.imported.lock().is_empty());
+	}
+
+	#[test]
+	fn validators_are_refetched_on_session_change() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let api = Arc::new(TestApi::default());
+		let factory = factory(api.clone(), NoNetwork, runtime.executor());
+		let key = Arc::new(Keyring::Alice.pair());
+
+		bft::Environment::init(&factory, &genesis_header(), &[], key.clone()).unwrap();
+		bft::Environment::init(&factory, &genesis_header(), &[], key.clone()).unwrap();
+		assert_eq!(*api.validator_fetches.lock(), 1);
+
+		*api.session_index.lock() += 1;
+		bft::Environment::init(&factory, &genesis_header(), &[], key).unwrap();
+		assert_eq!(*api.validator_fetches.lock(), 2);
+	}
+
+	#[test]
+	fn validators_are_cached_by_parent_without_session_index() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let api = Arc::new(TestApi { no_session_index: true, ..Default::default() });
+		let factory = factory(api.clone(), NoNetwork, runtime.executor());
+		let key = Arc::new(Keyring::Alice.pair());
+
+		bft::Environment::init(&factory, &genesis_header(), &[], key.clone()).unwrap();
+		bft::Environment::init(&factory, &genesis_header(), &[], key).unwrap();
+		assert_eq!(*api.validator_fetches.lock(), 1);
+	}
+
+	#[test]
+	fn consensus_context_is_fetched_in_one_call() {
+		use runtime_primitives::traits::{Hash as HashT, BlakeTwo256};
+
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let validators: Vec<AccountId> = vec![Keyring::Alice.to_raw_public().into(), Keyring::Bob.to_raw_public().into()];
+		let api = Arc::new(TestApi { validators: validators.clone(), combined_context: true, ..Default::default() });
+		let factory = factory(api.clone(), NoNetwork, runtime.executor());
+
+		let (proposer, _, _) = bft::Environment::init(&factory, &genesis_header(), &[], Arc::new(Keyring::Alice.pair())).unwrap();
+
+		assert_eq!(proposer.random_seed, BlakeTwo256::hash(&[7; 32]));
+		assert_eq!(proposer.validators(), &validators[..]);
+		assert_eq!(*api.context_calls.lock(), 1);
+		assert_eq!(*api.validator_fetches.lock(), 0);
+	}
+
+	#[test]
+	fn init_rejects_unknown_parent() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let factory = factory(Arc::new(TestApi { unknown_blocks: true, ..Default::default() }), NoNetwork, runtime.executor());
+		let parent = genesis_header();
+
+		match bft::Environment::init(&factory, &parent, &[], Arc::new(Keyring::Alice.pair())) {
+			Err(Error(ErrorKind::UnknownParent(hash), _)) => assert_eq!(hash, parent.hash()),
+			other => panic!("expected unknown parent, got {:?}", other.map(|_| ())),
+		}
+	}
+
+	#[test]
+	fn init_communicates_with_paired_session_key() {
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let alice: AccountId = Keyring::Alice.to_raw_public().into();
+		let bob: AccountId = Keyring::Bob.to_raw_public().into();
+		let alice_session: SessionKey = [42; 32].into();
+		let api = Arc::new(TestApi {
+			validators: vec![bob, alice],
+			session_keys: Some(vec![Keyring::Bob.to_raw_public().into(), alice_session]),
+			..Default::default()
+		});
+		let network = LocalIdNetwork::default();
+		let factory = factory(api, &network, runtime.executor());
+
+		bft::Environment::init(&factory, &genesis_header(), &[], Arc::new(Keyring::Alice.pair())).unwrap();
+
+		assert_eq!(*network.0.lock(), Some(alice_session));
+	}
+
+	#[test]
+	fn structurally_invalid_bake_is_an_error() {
+		let proposer = proposer(TestApi { misnumbered_blocks: true, ..Default::default() });
+
+		match bft::Proposer::propose(&proposer) {
+			Err(Error(ErrorKind::BadBake(_), _)) => {}
+			other => panic!("expected a bad bake, got {:?}", other.map(|_| ())),
+		}
+	}
+
+	#[test]
+	fn propose_skips_inapplicable_extrinsics() {
+		let good = signed_extrinsic(Keyring::Alice, 0);
+		let bad = signed_extrinsic(Keyring::Bob, 0);
+		let proposer = proposer(TestApi {
+			known_addresses: true,
+			inapplicable: vec![bad.clone()],
+			..Default::default()
+		});
+		proposer.transaction_pool.submit_at(&proposer.parent_id, vec![good.clone(), bad.clone()]).unwrap();
+
+		let block = bft::Proposer::propose(&proposer).unwrap();
+
+		assert!(block.extrinsics.contains(&good));
+		assert!(!block.extrinsics.contains(&bad));
+		assert_eq!(proposer.transaction_pool.light_status().transaction_count, 1);
+	}
+
+	#[test]
+	fn culled_extrinsics_record_why() {
+		let bad = signed_extrinsic(Keyring::Bob, 0);
+		let proposer = proposer(TestApi {
+			known_addresses: true,
+			inapplicable: vec![bad.clone()],
+			..Default::default()
+		});
+		let hash = *proposer.transaction_pool.submit_one(&proposer.parent_id, bad).unwrap().verified.hash();
+
+		bft::Proposer::propose(&proposer).unwrap();
+
+		assert_eq!(proposer.transaction_pool.removal_reason(&hash), Some(RemovalReason::InvalidDuringProposal));
+	}
+
+	#[test]
+	fn transactions_after_a_gap_are_not_checked() {
+		let xts: Vec<_> = (0..3).map(|i| signed_extrinsic(Keyring::Alice, i)).collect();
+		let proposer = proposer(TestApi {
+			known_addresses: true,
+			inapplicable: vec![xts[0].clone()],
+			..Default::default()
+		});
+		proposer.transaction_pool.submit_at(&proposer.parent_id, xts.clone()).unwrap();
+
+		let block = bft::Proposer::propose(&proposer).unwrap();
+
+		assert_eq!(*proposer.client.apply_checks.lock(), 1);
+		assert!(block.extrinsics.iter().all(|xt| !xt.is_signed()));
+	}
+
+	#[test]
+	fn inherent_provider_data_is_proposed() {
+		use node_runtime::{Call, ConsensusCall};
+
+		struct ReportEveryone;
+
+		impl InherentProvider for ReportEveryone {
+			fn provide_inherent_data(&self, _parent: &BlockId, inherent_data: &mut InherentData) {
+				inherent_data.offline_indices = vec![0, 1];
+			}
+		}
+
+		let mut proposer = proposer(TestApi { validators: vec![[10; 32].into(), [11; 32].into()], ..Default::default() });
+		proposer.inherent_provider = Some(Arc::new(ReportEveryone));
+
+		let block = bft::Proposer::propose(&proposer).unwrap();
+		let expected = node_runtime::UncheckedExtrinsic::new_unsigned(Call::Consensus(ConsensusCall::note_offline(vec![0, 1])));
+
+		assert_eq!(block.extrinsics.len(), 2);
+		assert_eq!(block.extrinsics[1].encode(), expected.encode());
+	}
+
+	#[test]
+	fn inherent_validator_vetoes_proposal() {
+		struct TimestampRange(Timestamp, Timestamp);
+
+		impl InherentValidator for TimestampRange {
+			fn validate_inherents(&self, _parent: &BlockId, inherent_data: &InherentData) -> Result<(), String> {
+				if inherent_data.timestamp < self.0 || inherent_data.timestamp > self.1 {
+					return Err(format!("timestamp {} out of range", inherent_data.timestamp));
+				}
+				Ok(())
+			}
+		}
+
+		let mut proposer = proposer(TestApi::default());
+		let block = bft::Proposer::propose(&proposer).unwrap();
+		let now = current_timestamp();
+		proposer.inherent_validator = Some(Arc::new(TimestampRange(now + 60, now + 120)));
+
+		assert_eq!(bft::Proposer::evaluate(&proposer, &block).wait().unwrap(), EvaluationOutcome::Invalid);
+	}
+
+	#[test]
+	fn round_robin_mode_interleaves_senders() {
+		let mut proposer = proposer(TestApi { known_addresses: true, ..Default::default() });
+		let alice: Vec<_> = (0..3).map(|i| signed_extrinsic(Keyring::Alice, i)).collect();
+		let bob = signed_extrinsic(Keyring::Bob, 0);
+		proposer.transaction_pool.submit_at(&proposer.parent_id, alice.iter().cloned()).unwrap();
+		proposer.transaction_pool.submit_one(&proposer.parent_id, bob.clone()).unwrap();
+		proposer.inclusion_mode = InclusionMode::RoundRobin;
+
+		let block = bft::Proposer::propose(&proposer).unwrap();
+		let position = |xt: &UncheckedExtrinsic| block.extrinsics.iter().position(|included| included == xt).unwrap();
+
+		assert!(position(&bob) < position(&alice[1]));
+		assert!(position(&alice[0]) < position(&alice[1]));
+		assert!(position(&alice[1]) < position(&alice[2]));
+	}
+
+	#[test]
+	fn packing_stops_at_runtime_block_length() {
+		let proposer = proposer(TestApi { known_addresses: true, ..Default::default() });
+		let xts = [Keyring::Alice, Keyring::Bob, Keyring::Charlie, Keyring::Dave].iter()
+			.map(|&who| signed_extrinsic(who, 0))
+			.collect::<Vec<_>>();
+		let size = proposer.transaction_pool.submit_at(&proposer.parent_id, xts.clone()).unwrap()[0].verified.encoded_size();
+		*proposer.client.max_block_length.lock() = Some(2 * size + 1);
+
+		bft::Proposer::propose(&proposer).unwrap();
+
+		assert_eq!(proposer.last_block_bytes(), 2 * size);
+	}
+
+	#[test]
+	fn concurrent_proposal_on_same_parent_is_refused() {
+		let proposer = proposer(TestApi { known_addresses: true, ..Default::default() });
+		proposer.transaction_pool.submit_one(&proposer.parent_id, signed_extrinsic(Keyring::Alice, 0)).unwrap();
+
+		// another proposal on the same parent is in progress.
+		let in_progress = ProposingGuard::acquire(&proposer.proposing, proposer.parent_hash).unwrap();
+		match bft::Proposer::propose(&proposer) {
+			Err(Error(ErrorKind::ConcurrentProposal(parent), _)) => assert_eq!(parent, proposer.parent_hash),
+			other => panic!("unexpected result: {:?}", other.map(|_| ())),
+		}
+		assert_eq!(proposer.transaction_pool.light_status().transaction_count, 1);
+
+		drop(in_progress);
+		assert_eq!(bft::Proposer::propose(&proposer).unwrap().extrinsics.len(), 2);
+	}
+
+	#[test]
+	fn packing_stops_at_max_extrinsics() {
+		let mut proposer = proposer(TestApi { known_addresses: true, ..Default::default() });
+		let xts = [Keyring::Alice, Keyring::Bob, Keyring::Charlie, Keyring::Dave, Keyring::Eve].iter()
+			.map(|&who| signed_extrinsic(who, 0))
+			.collect::<Vec<_>>();
+		proposer.transaction_pool.submit_at(&proposer.parent_id, xts).unwrap();
+		proposer.max_extrinsics = Some(2);
+
+		let block = bft::Proposer::propose(&proposer).unwrap();
+
+		// the timestamp inherent and two transactions.
+		assert_eq!(block.extrinsics.len(), 3);
+	}
+
+	#[test]
+	fn trial_propose_reports_failing_execution() {
+		let proposer = proposer(TestApi { known_addresses: true, bad_blocks: true, ..Default::default() });
+		proposer.transaction_pool.submit_one(&proposer.parent_id, signed_extrinsic(Keyring::Alice, 0)).unwrap();
+
+		let (block, good) = proposer.trial_propose().unwrap();
+
+		assert!(!good);
+		assert_eq!(block.extrinsics.len(), 2);
+		assert_eq!(proposer.transaction_pool.light_status().transaction_count, 1);
+	}
+
+	#[test]
+	fn inclusion_policy_vetoes_are_respected() {
+		// rejects transactions taking more than half of what's left of a small budget.
+		struct HalfOfRemaining(usize);
+
+		impl InclusionPolicy for HalfOfRemaining {
+			fn include(&self, _extrinsic: &UncheckedExtrinsic, encoded_size: usize, remaining: usize) -> bool {
+				let used = MAX_TRANSACTIONS_SIZE - remaining;
+				encoded_size <= self.0.saturating_sub(used) / 2
+			}
+		}
+
+		let mut proposer = proposer(TestApi { known_addresses: true, ..Default::default() });
+		let xts = [Keyring::Alice, Keyring::Bob, Keyring::Charlie, Keyring::Dave].iter()
+			.map(|&who| signed_extrinsic(who, 0))
+			.collect::<Vec<_>>();
+		let size = proposer.transaction_pool.submit_at(&proposer.parent_id, xts.clone()).unwrap()[0].verified.encoded_size();
+		proposer.inclusion_policy = Some(Arc::new(HalfOfRemaining(3 * size)));
+
+		let block = bft::Proposer::propose(&proposer).unwrap();
+
+		// the first two take at most half of what's left; the rest would take more.
+		assert_eq!(xts.iter().filter(|xt| block.extrinsics.contains(xt)).count(), 2);
+		assert_eq!(proposer.transaction_pool.light_status().transaction_count, 4);
+	}
+
+	#[test]
+	fn zero_seed_rotates_through_all_proposers() {
+		let proposer = proposer(TestApi::default());
+		assert_eq!(proposer.random_seed, Hash::default());
+
+		let rotation: Vec<_> = (0..8).map(|round| proposer.primary_index(round, 4)).collect();
+
+		assert_eq!(rotation, vec![0, 1, 2, 3, 0, 1, 2, 3]);
+	}
+
+	#[test]
+	fn rotation_schedule_follows_the_seed() {
+		let mut proposer = proposer(TestApi::default());
+		let authorities: Vec<AuthorityId> = (0..3u8).map(|i| [i; 32].into()).collect();
+		let mut seed = [0; 32];
+		seed[31] = 5;
+		proposer.random_seed = seed.into();
+
+		// the seed offsets the rotation by 5 % 3.
+		let expected: Vec<_> = [2, 0, 1, 2, 0].iter().map(|&i| authorities[i]).collect();
+		assert_eq!(proposer.rotation_schedule(&authorities, 5), expected);
+		assert!(proposer.rotation_schedule(&[], 5).is_empty());
+	}
+
+	#[test]
+	fn round_observer_is_notified_of_round_end() {
+		#[derive(Default)]
+		struct RecordingObserver(Mutex<Vec<(usize, bool, AccountId)>>);
+
+		impl RoundObserver for RecordingObserver {
+			fn on_round_end(&self, round_number: usize, was_proposed: bool, primary_validator: AccountId) {
+				self.0.lock().push((round_number, was_proposed, primary_validator));
+			}
+		}
+
+		let validators: Vec<AccountId> = vec![[10; 32].into(), [11; 32].into()];
+		let mut proposer = proposer(TestApi { validators: validators.clone(), ..Default::default() });
+		let observer = Arc::new(RecordingObserver::default());
+		proposer.round_observer = Some(observer.clone());
+
+		bft::Proposer::on_round_end(&proposer, 3, false);
+
+		let primary = validators[proposer.primary_index(3, validators.len())];
+		assert_eq!(*observer.0.lock(), vec![(3, false, primary)]);
+	}
+
+	#[test]
+	fn author_reward_is_observed_on_propose() {
+		#[derive(Default)]
+		struct RecordingObserver(Mutex<Vec<(BlockNumber, Balance)>>);
+
+		impl RoundObserver for RecordingObserver {
+			fn on_round_end(&self, _: usize, _: bool, _: AccountId) {}
+
+			fn on_author_reward(&self, block_number: BlockNumber, reward: Balance) {
+				self.0.lock().push((block_number, reward));
+			}
+		}
+
+		let mut proposer = proposer(TestApi { author_reward: Some(50), ..Default::default() });
+		let observer = Arc::new(RecordingObserver::default());
+		proposer.round_observer = Some(observer.clone());
+
+		let block = bft::Proposer::propose(&proposer).unwrap();
+
+		assert_eq!(*observer.0.lock(), vec![(block.header.number, 50)]);
+	}
+
+	#[test]
+	fn offline_reports_can_be_disabled() {
+		let validators: Vec<AccountId> = vec![[10; 32].into(), [11; 32].into()];
+		let mut proposer = proposer(TestApi { validators: validators.clone(), ..Default::default() });
+		proposer.offline.with_mut(|offline| offline.force_offline(validators[0]));
+
+		assert_eq!(bft::Proposer::propose(&proposer).unwrap().extrinsics.len(), 2);
+
+		proposer.report_offline = false;
+		assert_eq!(bft::Proposer::propose(&proposer).unwrap().extrinsics.len(), 1);
+	}
+
+	#[test]
+	fn proposed_block_size_is_recorded() {
+		let proposer = proposer(TestApi { known_addresses: true, ..Default::default() });
+		let xts = vec![signed_extrinsic(Keyring::Alice, 0), signed_extrinsic(Keyring::Bob, 0)];
+		let expected: usize = proposer.transaction_pool.submit_at(&proposer.parent_id, xts).unwrap()
+			.iter()
+			.map(|xt| xt.verified.encoded_size())
+			.sum();
+
+		bft::Proposer::propose(&proposer).unwrap();
+
+		assert_eq!(proposer.last_block_bytes(), expected);
+	}
+
+	thread_local! {
+		static LOGGED: ::std::cell::RefCell<Vec<String>> = Default::default();
+	}
+
+	// Keeps the messages logged on each thread apart, so tests can run in parallel.
+	struct ThreadLogger;
+
+	impl ::log::Log for ThreadLogger {
+		fn enabled(&self, _metadata: &::log::LogMetadata) -> bool {
+			true
+		}
+
+		fn log(&self, record: &::log::LogRecord) {
+			LOGGED.with(|logged| logged.borrow_mut().push(record.args().to_string()));
+		}
+	}
+
+	fn capture_logs() {
+		static INIT: ::std::sync::Once = ::std::sync::ONCE_INIT;
+		INIT.call_once(|| {
+			::log::set_logger(|max_level| {
+				max_level.set(::log::LogLevelFilter::Trace);
+				Box::new(ThreadLogger)
+			}).expect("no other logger is set in tests; qed");
+		});
+	}
+
+	#[test]
+	fn proposer_log_lines_carry_context() {
+		capture_logs();
+		let proposer = proposer(TestApi::default());
+		let context = proposer.log_context().to_string();
+
+		let block = bft::Proposer::propose(&proposer).unwrap();
+		// the evaluation is logged before the vote is resolved.
+		let _ = bft::Proposer::evaluate(&proposer, &block);
+
+		LOGGED.with(|logged| {
+			let logged = logged.borrow();
+			let with_context = |message: &str| logged.iter()
+				.any(|line| line.starts_with(&context) && line.contains(message));
+
+			assert!(with_context("Proposing block"));
+			assert!(with_context("Evaluating proposal"));
+		});
+	}
+
+	#[test]
+	fn session_start_log_names_the_chain_spec() {
+		capture_logs();
+		let runtime = tokio::runtime::Runtime::new().unwrap();
+		let factory = factory(Arc::new(TestApi::default()), NoNetwork, runtime.executor());
+		let parent = genesis_header();
+
+		bft::Environment::init(&factory, &parent, &[], Arc::new(Keyring::Alice.pair())).unwrap();
+
+		LOGGED.with(|logged| {
+			assert!(logged.borrow().iter()
+				.any(|line| line.starts_with("Starting consensus session of test-node on top of parent")));
+		});
+	}
+
+	#[test]
+	fn local_proposer_follows_round_robin() {
+		use bft::Proposer as BftProposer;
+
+		let proposer = proposer(TestApi::default());
+		let authorities: Vec<AuthorityId> = vec![Keyring::Alice.to_raw_public().into(), Keyring::Bob.to_raw_public().into()];
+		let local_round = (0..2).find(|&round| proposer.round_proposer(round, &authorities) == authorities[0]).unwrap();
+
+		assert!(proposer.is_local_proposer(local_round, &authorities));
+		assert!(!proposer.is_local_proposer(local_round + 1, &authorities));
+		assert!(!proposer.is_local_proposer(local_round, &[]));
+	}
+
+	#[test]
+	fn propose_with_uses_given_extrinsics_only() {
+		let proposer = proposer(TestApi { known_addresses: true, ..Default::default() });
+		proposer.transaction_pool.submit_one(&proposer.parent_id, signed_extrinsic(Keyring::Charlie, 0)).unwrap();
+		let given = vec![signed_extrinsic(Keyring::Alice, 0), signed_extrinsic(Keyring::Bob, 0)];
+
+		let block = proposer.propose_with(given.clone()).unwrap();
+
+		// just the timestamp inherent precedes them.
+		assert_eq!(block.extrinsics.len(), 3);
+		assert_eq!(&block.extrinsics[1..], &given[..]);
+	}
+
+	#[test]
+	fn proposal_counts_match_baked_extrinsics() {
+		let proposer = proposer(TestApi { known_addresses: true, ..Default::default() });
+		let xts = vec![signed_extrinsic(Keyring::Alice, 0), signed_extrinsic(Keyring::Bob, 0)];
+		proposer.transaction_pool.submit_at(&proposer.parent_id, xts).unwrap();
+
+		let block = bft::Proposer::propose(&proposer).unwrap();
+		let inherents = block.extrinsics.iter().filter(|xt| !xt.is_signed()).count();
+
+		assert_eq!(proposer.last_proposal_counts(), ProposalCounts {
+			pending_total: 2,
+			included: block.extrinsics.len() - inherents,
+		});
+	}
+
+	#[test]
+	fn empty_proposals_are_suppressed_within_interval() {
+		let interval = Duration::from_secs(60);
+		let mut proposer = proposer(TestApi::default());
+		proposer.min_empty_interval = Some(interval);
+
+		match bft::Proposer::propose(&proposer) {
+			Err(ref e @ Error(ErrorKind::EmptyBlockSuppressed, _)) => {
+				// the agreement goes on without a proposal from us.
+				assert!(<Proposer<TestApi> as bft::Proposer<Block>>::is_round_skipped(e));
+			}
+			other => panic!("Expected empty block to be suppressed, got {:?}", other.map(|b| b.header)),
+		}
+
+		*proposer.last_proposal.lock() -= interval;
+		assert!(bft::Proposer::propose(&proposer).is_ok());
+
+		// empty blocks don't start the interval over.
+		assert!(bft::Proposer::propose(&proposer).is_ok());
+	}
+
+	#[test]
+	fn slow_evaluation_votes_against() {
+		let mut proposer = proposer(TestApi {
+			evaluate_delay: Some(Duration::from_secs(2)),
+			..Default::default()
+		});
+		let block = bft::Proposer::propose(&proposer).unwrap();
+		let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+		proposer.evaluate_deadline = Duration::from_millis(100);
+		assert_eq!(runtime.block_on(bft::Proposer::evaluate(&proposer, &block)).unwrap(), EvaluationOutcome::Invalid);
+
+		proposer.evaluate_deadline = Duration::from_secs(10);
+		assert_eq!(runtime.block_on(bft::Proposer::evaluate(&proposer, &block)).unwrap(), EvaluationOutcome::Valid);
+	}
+
+	#[test]
+	fn evaluations_beyond_the_limit_wait() {
+		let mut proposer = proposer(TestApi {
+			evaluate_delay: Some(Duration::from_millis(100)),
+			..Default::default()
+		});
+		let block = bft::Proposer::propose(&proposer).unwrap();
+		let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+		proposer.evaluation_limit = EvaluationLimit::new(1);
+		let evaluations: Vec<_> = (0..3).map(|_| bft::Proposer::evaluate(&proposer, &block)).collect();
+		let outcomes = runtime.block_on(future::join_all(evaluations)).unwrap();
+
+		assert_eq!(outcomes, vec![EvaluationOutcome::Valid; 3]);
+		assert_eq!(*proposer.client.evaluations.lock(), 3);
+		assert_eq!(proposer.client.evaluations_in_flight.lock().1, 1);
+	}
+
+	#[test]
+	fn abandoned_evaluations_are_skipped() {
+		let mut proposer = proposer(TestApi {
+			evaluate_delay: Some(Duration::from_millis(300)),
+			..Default::default()
+		});
+		let block = bft::Proposer::propose(&proposer).unwrap();
+		let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+		proposer.evaluation_limit = EvaluationLimit::new(1);
+		proposer.evaluate_deadline = Duration::from_millis(100);
+		let evaluations: Vec<_> = (0..3).map(|_| bft::Proposer::evaluate(&proposer, &block)).collect();
+		let outcomes = runtime.block_on(future::join_all(evaluations)).unwrap();
+		assert_eq!(outcomes, vec![EvaluationOutcome::Invalid; 3]);
+
+		// only the evaluation executing at the deadline runs; the queued ones don't hold up later ones.
+		proposer.evaluated.lock().clear();
+		proposer.evaluate_deadline = Duration::from_secs(10);
+		assert_eq!(runtime.block_on(bft::Proposer::evaluate(&proposer, &block)).unwrap(), EvaluationOutcome::Valid);
+		assert_eq!(*proposer.client.evaluations.lock(), 2);
+	}
+
+	#[test]
+	fn transient_evaluation_errors_are_retried() {
+		let mut proposer = proposer(TestApi::default());
+		let block = bft::Proposer::propose(&proposer).unwrap();
+		let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+		proposer.evaluate_retries = 1;
+		*proposer.client.transient_failures.lock() = 1;
+		assert_eq!(runtime.block_on(bft::Proposer::evaluate(&proposer, &block)).unwrap(), EvaluationOutcome::Valid);
+
+		proposer.evaluate_retries = 0;
+		proposer.evaluated.lock().clear();
+		*proposer.client.transient_failures.lock() = 1;
+		assert!(runtime.block_on(bft::Proposer::evaluate(&proposer, &block)).is_err());
+	}
+
+	#[test]
+	fn repeated_evaluations_are_cached() {
+		let proposer = proposer(TestApi::default());
+		let block = bft::Proposer::propose(&proposer).unwrap();
+		let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+		for _ in 0..2 {
+			assert_eq!(runtime.block_on(bft::Proposer::evaluate(&proposer, &block)).unwrap(), EvaluationOutcome::Valid);
+		}
+
+		assert_eq!(*proposer.client.evaluations.lock(), 1);
+	}
+
+	#[test]
+	fn vote_delay_is_capped() {
+		let mut proposer = proposer(TestApi::default());
+		let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+		let block_at = |proposer: &Proposer<TestApi>, timestamp| {
+			let inherent_data = InherentData { timestamp, offline_indices: Vec::new() };
+			proposer.client.build_block(&proposer.parent_id, inherent_data).unwrap().bake().unwrap()
+		};
+
+		let far_future = block_at(&proposer, current_timestamp() + 3 * 60 * 60);
+		assert_eq!(bft::Proposer::evaluate(&proposer, &far_future).wait().unwrap(), EvaluationOutcome::Invalid);
+
+		// a proposer insisting on a later timestamp doesn't hold up the vote for long.
+		proposer.minimum_timestamp = current_timestamp() + 60 * 60;
+		proposer.max_vote_delay = Duration::from_secs(1);
+		let block = block_at(&proposer, current_timestamp());
+		let started = Instant::now();
+		assert_eq!(runtime.block_on(bft::Proposer::evaluate(&proposer, &block)).unwrap(), EvaluationOutcome::Valid);
+		assert!(started.elapsed() < Duration::from_secs(10));
+	}
+
+	#[test]
+	fn timestamps_within_skew_tolerance_are_accepted() {
+		let proposer = proposer(TestApi::default());
+		let timestamp = current_timestamp();
+		let inherent_data = InherentData { timestamp, offline_indices: Vec::new() };
+		let block = proposer.client.build_block(&proposer.parent_id, inherent_data).unwrap().bake().unwrap();
+		let evaluate = |skew_tolerance| evaluation::evaluate_initial(
+			&block,
+			timestamp - 3,
+			0,
+			skew_tolerance,
+			&proposer.parent_hash,
+			proposer.parent_number,
+		);
+
+		assert!(evaluate(Duration::from_secs(5)).is_ok());
+		match evaluate(Duration::from_secs(1)) {
+			Err(evaluation::Error(evaluation::ErrorKind::TimestampInFuture, _)) => {}
+			other => panic!("expected future timestamp to be rejected, got {:?}", other.map(|_| ())),
+		}
+	}
+
+	#[test]
+	fn cancelled_evaluation_resolves_promptly() {
+		let mut proposer = proposer(TestApi::default());
+		let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+		let block = bft::Proposer::propose(&proposer).unwrap();
+
+		// a good vote would be held back for a minute.
+		proposer.minimum_timestamp = current_timestamp() + 60 * 60;
+		let evaluation = bft::Proposer::evaluate(&proposer, &block);
+		proposer.cancel_evaluations();
+
+		let started = Instant::now();
+		match runtime.block_on(evaluation) {
+			Err(Error(ErrorKind::EvaluationCancelled, _)) => {}
+			other => panic!("expected cancellation, got {:?}", other),
+		}
+		assert!(started.elapsed() < Duration::from_secs(10));
+	}
+
+	#[test]
+	fn mismatched_extrinsics_root_is_rejected_without_execution() {
+		let proposer = proposer(TestApi::default());
+		let mut block = bft::Proposer::propose(&proposer).unwrap();
+		block.header.extrinsics_root = [0xff; 32].into();
+
+		assert_eq!(bft::Proposer::evaluate(&proposer, &block).wait().unwrap(), EvaluationOutcome::Invalid);
+		assert_eq!(*proposer.client.evaluations.lock(), 0);
+	}
+
+	#[test]
+	fn duplicate_extrinsics_are_rejected_without_execution() {
+		let proposer = proposer(TestApi { known_addresses: true, ..Default::default() });
+		let xt = signed_extrinsic(Keyring::Alice, 0);
+		let block = proposer.propose_with(vec![xt.clone(), xt]).unwrap();
+
+		assert_eq!(bft::Proposer::evaluate(&proposer, &block).wait().unwrap(), EvaluationOutcome::Invalid);
+		assert_eq!(*proposer.client.evaluations.lock(), 0);
+	}
 }
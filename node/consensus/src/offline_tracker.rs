@@ -16,9 +16,12 @@
 
 //! Tracks offline validators.
 
-use node_primitives::AccountId;
+use node_primitives::{AccountId, Balance};
+use parking_lot::RwLock;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
 use std::time::{Instant, Duration};
 
 // time before we report a validator.
@@ -27,6 +30,8 @@ const REPORT_TIME: Duration = Duration::from_secs(60 * 5);
 struct Observed {
 	last_round_end: Instant,
 	offline_since: Instant,
+	// outcomes of the most recent rounds, if judging by a window of rounds.
+	recent: VecDeque<bool>,
 }
 
 impl Observed {
@@ -35,34 +40,93 @@ impl Observed {
 		Observed {
 			last_round_end: now,
 			offline_since: now,
+			recent: VecDeque::new(),
 		}
 	}
 
-	fn note_round_end(&mut self, was_online: bool) {
+	fn note_round_end(&mut self, was_online: bool, round_window: Option<usize>) {
 		let now = Instant::now();
 
 		self.last_round_end = now;
 		if was_online {
 			self.offline_since = now;
 		}
+
+		if let Some(window) = round_window {
+			self.recent.push_back(was_online);
+			while self.recent.len() > window {
+				self.recent.pop_front();
+			}
+		}
 	}
 
-	fn is_active(&self) -> bool {
+	fn offline_for(&self) -> Duration {
+		// can happen if clocks are not monotonic
+		if self.offline_since > self.last_round_end { return Duration::from_secs(0) }
+		self.last_round_end.duration_since(self.offline_since)
+	}
+
+	fn is_active(&self, round_window: Option<usize>) -> bool {
+		if let Some(window) = round_window {
+			return self.recent.len() < window || self.recent.iter().any(|&online| online);
+		}
+
 		// can happen if clocks are not monotonic
 		if self.offline_since > self.last_round_end { return true }
 		self.last_round_end.duration_since(self.offline_since) < REPORT_TIME
 	}
 }
 
+/// Reason why an offline report disagrees with our view of things.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Inconsistency {
+	/// The reported index does not refer to any validator in the set.
+	UnknownIndex(u32),
+	/// The validator at the reported index is online as far as we are concerned.
+	ThoughtOnline(u32),
+}
+
+impl fmt::Display for Inconsistency {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Inconsistency::UnknownIndex(i) => write!(f, "reported index {} is not a validator", i),
+			Inconsistency::ThoughtOnline(i) => write!(f, "validator at index {} is not offline", i),
+		}
+	}
+}
+
 /// Tracks offline validators and can issue a report for those offline.
 pub struct OfflineTracker {
 	observed: HashMap<AccountId, Observed>,
+	// number of blocks each validator has been in the set for.
+	present_for: HashMap<AccountId, u64>,
+	grace_blocks: u64,
+	round_window: Option<usize>,
 }
 
 impl OfflineTracker {
 	/// Create a new tracker.
 	pub fn new() -> Self {
-		OfflineTracker { observed: HashMap::new() }
+		OfflineTracker::with_grace_blocks(0)
+	}
+
+	/// Create a new tracker which doesn't report validators until they have
+	/// been in the validator set for `grace_blocks` blocks.
+	pub fn with_grace_blocks(grace_blocks: u64) -> Self {
+		OfflineTracker {
+			observed: HashMap::new(),
+			present_for: HashMap::new(),
+			grace_blocks,
+			round_window: None,
+		}
+	}
+
+	/// Judge validators by the outcomes of their last `rounds` rounds only,
+	/// reporting those which missed all of them, instead of by how long they
+	/// have been offline for.
+	pub fn with_round_window(mut self, rounds: usize) -> Self {
+		self.round_window = Some(rounds);
+		self
 	}
 
 	/// Note new consensus is starting with the given set of validators.
@@ -71,13 +135,18 @@ impl OfflineTracker {
 
 		let set: HashSet<_> = validators.iter().cloned().collect();
 		self.observed.retain(|k, _| set.contains(k));
+		self.present_for.retain(|k, _| set.contains(k));
+
+		for v in set {
+			*self.present_for.entry(v).or_insert(0) += 1;
+		}
 	}
 
 	/// Note that a round has ended.
 	pub fn note_round_end(&mut self, validator: AccountId, was_online: bool) {
 		self.observed.entry(validator)
 			.or_insert_with(Observed::new)
-			.note_round_end(was_online);
+			.note_round_end(was_online, self.round_window);
 	}
 
 	/// Generate a vector of indices for offline account IDs.
@@ -92,22 +161,122 @@ impl OfflineTracker {
 			.collect()
 	}
 
+	/// Generate a vector of indices for offline account IDs along with the
+	/// time each has been offline for.
+	pub fn scored_reports(&self, validators: &[AccountId]) -> Vec<(u32, Duration)> {
+		validators.iter()
+			.enumerate()
+			.filter_map(|(i, v)| match self.observed.get(v) {
+				Some(o) if !o.is_active(self.round_window) && !self.in_grace(v) => Some((i as u32, o.offline_for())),
+				_ => None,
+			})
+			.collect()
+	}
+
+	/// Generate a vector of at most `max` indices for offline account IDs, keeping
+	/// those with the most at stake; ties go to those which have been offline the longest.
+	///
+	/// Stakes are only looked up with `stake_of` if more than `max` are offline, and
+	/// only for those. In that case the reports come in descending order of stake,
+	/// otherwise in validator order.
+	pub fn stake_weighted_reports<F, E>(&self, validators: &[AccountId], max: usize, mut stake_of: F) -> Result<Vec<u32>, E>
+		where F: FnMut(&AccountId) -> Result<Balance, E>
+	{
+		let mut scored = self.scored_reports(validators);
+		if scored.len() > max {
+			let mut weighted = Vec::with_capacity(scored.len());
+			for (i, offline_for) in scored {
+				weighted.push((i, stake_of(&validators[i as usize])?, offline_for));
+			}
+			weighted.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.cmp(&a.2)).then(a.0.cmp(&b.0)));
+			weighted.truncate(max);
+
+			scored = weighted.into_iter().map(|(i, _, offline_for)| (i, offline_for)).collect();
+		}
+
+		Ok(scored.into_iter().map(|(i, _)| i).collect())
+	}
+
+	/// Generate a vector of at most `max` indices for offline account IDs,
+	/// keeping those which have been offline the longest.
+	pub fn worst_reports(&self, validators: &[AccountId], max: usize) -> Vec<u32> {
+		let mut scored = self.scored_reports(validators);
+		if scored.len() > max {
+			scored.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+			scored.truncate(max);
+			scored.sort_by_key(|&(i, _)| i);
+		}
+
+		scored.into_iter().map(|(i, _)| i).collect()
+	}
+
 	/// Whether reports on a validator set are consistent with our view of things.
-	pub fn check_consistency(&self, validators: &[AccountId], reports: &[u32]) -> bool {
-		reports.iter().cloned().all(|r| {
+	///
+	/// Returns the first discrepancy found, if any.
+	pub fn check_consistency(&self, validators: &[AccountId], reports: &[u32]) -> Result<(), Inconsistency> {
+		for &r in reports {
 			let v = match validators.get(r as usize) {
 				Some(v) => v,
-				None => return false,
+				None => return Err(Inconsistency::UnknownIndex(r)),
 			};
 
 			// we must think all validators reported externally are offline.
-			let thinks_online = self.is_online(v);
-			!thinks_online
-		})
+			if self.is_online(v) {
+				return Err(Inconsistency::ThoughtOnline(r));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Compare our view of a validator set with another tracker's.
+	///
+	/// Returns `(index, we think offline, other thinks offline)` for every
+	/// validator the two trackers disagree on.
+	pub fn diff(&self, other: &OfflineTracker, validators: &[AccountId]) -> Vec<(usize, bool, bool)> {
+		validators.iter()
+			.enumerate()
+			.map(|(i, v)| (i, !self.is_online(v), !other.is_online(v)))
+			.filter(|&(_, ours, theirs)| ours != theirs)
+			.collect()
+	}
+
+	#[cfg(test)]
+	pub(crate) fn force_offline(&mut self, validator: AccountId) {
+		let observed = self.observed.entry(validator).or_insert_with(Observed::new);
+		observed.offline_since -= REPORT_TIME + Duration::from_secs(5);
 	}
 
 	fn is_online(&self, v: &AccountId) -> bool {
-		self.observed.get(v).map(Observed::is_active).unwrap_or(true)
+		self.in_grace(v) || self.observed.get(v).map_or(true, |o| o.is_active(self.round_window))
+	}
+
+	fn in_grace(&self, v: &AccountId) -> bool {
+		self.present_for.get(v).cloned().unwrap_or(0) < self.grace_blocks
+	}
+}
+
+/// Offline tracker shared between proposers.
+///
+/// The tracker is only accessed through closures holding the lock for their
+/// whole duration, so a group of updates is observed either entirely or not at all.
+#[derive(Clone)]
+pub struct SharedOfflineTracker(Arc<RwLock<OfflineTracker>>);
+
+impl SharedOfflineTracker {
+	/// Share the given tracker.
+	pub fn new(tracker: OfflineTracker) -> Self {
+		SharedOfflineTracker(Arc::new(RwLock::new(tracker)))
+	}
+
+	/// Inspect the tracker.
+	pub fn with<F, T>(&self, f: F) -> T where F: FnOnce(&OfflineTracker) -> T {
+		f(&self.0.read())
+	}
+
+	/// Update the tracker atomically.
+	pub fn with_mut<F, T>(&self, f: F) -> T where F: FnOnce(&mut OfflineTracker) -> T {
+		f(&mut self.0.write())
 	}
 }
 
@@ -134,4 +303,137 @@ mod tests {
 		tracker.note_new_block(&[v, v3]);
 		assert_eq!(tracker.reports(&[v, v2, v3]), vec![0]);
 	}
+
+	#[test]
+	fn inconsistency_names_offending_index() {
+		let mut tracker = OfflineTracker::new();
+		let v = [0; 32].into();
+		let v2 = [1; 32].into();
+		tracker.note_round_end(v, true);
+		tracker.note_round_end(v2, true);
+
+		let slash_time = REPORT_TIME + Duration::from_secs(5);
+		tracker.observed.get_mut(&v).unwrap().offline_since -= slash_time;
+
+		assert_eq!(tracker.check_consistency(&[v, v2], &[0]), Ok(()));
+		assert_eq!(tracker.check_consistency(&[v, v2], &[0, 1]), Err(Inconsistency::ThoughtOnline(1)));
+		assert_eq!(tracker.check_consistency(&[v, v2], &[5]), Err(Inconsistency::UnknownIndex(5)));
+	}
+
+	#[test]
+	fn stake_weighted_reports_put_highest_stake_first() {
+		let mut tracker = OfflineTracker::new();
+		let validators: Vec<AccountId> = (0..4u8).map(|i| [i; 32].into()).collect();
+		for v in &validators {
+			tracker.note_round_end(*v, true);
+			tracker.observed.get_mut(v).unwrap().offline_since -= REPORT_TIME + Duration::from_secs(1);
+		}
+
+		let stakes = [10, 30, 20, 40];
+		let stake_of = |v: &AccountId| Ok::<_, ()>(stakes[v[0] as usize]);
+		assert_eq!(tracker.stake_weighted_reports(&validators, 2, stake_of), Ok(vec![3, 1]));
+
+		// stakes aren't looked up unless some reports have to be left out.
+		assert_eq!(tracker.stake_weighted_reports(&validators, 4, |_| Err(())), Ok(vec![0, 1, 2, 3]));
+	}
+
+	#[test]
+	fn worst_reports_keeps_longest_offline() {
+		let mut tracker = OfflineTracker::new();
+		let validators: Vec<AccountId> = (0..4u8).map(|i| [i; 32].into()).collect();
+		for v in &validators {
+			tracker.note_round_end(*v, true);
+		}
+
+		for (i, v) in validators.iter().enumerate() {
+			let slash_time = REPORT_TIME + Duration::from_secs(5 * (i as u64 + 1));
+			tracker.observed.get_mut(v).unwrap().offline_since -= slash_time;
+		}
+
+		assert_eq!(tracker.reports(&validators), vec![0, 1, 2, 3]);
+		assert_eq!(tracker.worst_reports(&validators, 2), vec![2, 3]);
+		assert_eq!(tracker.worst_reports(&validators, 10), vec![0, 1, 2, 3]);
+	}
+
+	#[test]
+	fn new_validators_get_grace_period() {
+		let mut tracker = OfflineTracker::with_grace_blocks(3);
+		let v = [0; 32].into();
+		let v2 = [1; 32].into();
+
+		tracker.note_new_block(&[v, v2]);
+		tracker.note_round_end(v, false);
+		tracker.force_offline(v);
+
+		tracker.note_new_block(&[v, v2]);
+		assert!(tracker.reports(&[v, v2]).is_empty());
+		assert_eq!(tracker.check_consistency(&[v, v2], &[0]), Err(Inconsistency::ThoughtOnline(0)));
+
+		tracker.note_new_block(&[v, v2]);
+		assert_eq!(tracker.reports(&[v, v2]), vec![0]);
+		assert_eq!(tracker.worst_reports(&[v, v2], 16), vec![0]);
+	}
+
+	#[test]
+	fn grouped_updates_are_observed_atomically() {
+		use std::thread;
+
+		let shared = SharedOfflineTracker::new(OfflineTracker::new());
+		let v: AccountId = [0; 32].into();
+		let v2: AccountId = [1; 32].into();
+
+		let writer = {
+			let shared = shared.clone();
+			thread::spawn(move || for _ in 0..1000 {
+				shared.with_mut(|t| {
+					t.force_offline(v);
+					t.force_offline(v2);
+				});
+				shared.with_mut(|t| {
+					t.note_round_end(v, true);
+					t.note_round_end(v2, true);
+				});
+			})
+		};
+
+		for _ in 0..1000 {
+			let reports = shared.with(|t| t.reports(&[v, v2]));
+			assert!(reports.is_empty() || reports == vec![0, 1], "observed partial update: {:?}", reports);
+		}
+
+		writer.join().unwrap();
+	}
+
+	#[test]
+	fn diff_pinpoints_disagreement() {
+		let validators: Vec<AccountId> = (0..3u8).map(|i| [i; 32].into()).collect();
+		let mut ours = OfflineTracker::new();
+		let mut theirs = OfflineTracker::new();
+
+		ours.force_offline(validators[0]);
+		theirs.force_offline(validators[0]);
+		theirs.force_offline(validators[2]);
+
+		assert_eq!(ours.diff(&theirs, &validators), vec![(2, false, true)]);
+		assert_eq!(theirs.diff(&ours, &validators), vec![(2, true, false)]);
+		assert!(ours.diff(&ours, &validators).is_empty());
+	}
+
+	#[test]
+	fn only_recent_rounds_count_with_round_window() {
+		let mut tracker = OfflineTracker::new().with_round_window(3);
+		let v: AccountId = [0; 32].into();
+
+		for &online in &[true, true, false, false] {
+			tracker.note_round_end(v, online);
+		}
+		assert!(tracker.reports(&[v]).is_empty());
+
+		tracker.note_round_end(v, false);
+		assert_eq!(tracker.reports(&[v]), vec![0]);
+		assert_eq!(tracker.observed[&v].recent.len(), 3);
+
+		tracker.note_round_end(v, true);
+		assert!(tracker.reports(&[v]).is_empty());
+	}
 }
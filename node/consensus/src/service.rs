@@ -21,11 +21,13 @@
 use std::thread;
 use std::time::{Duration, Instant};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use bft::{self, BftService};
 use client::{BlockchainEvents, ChainHead, BlockBody};
 use ed25519;
 use futures::prelude::*;
+use futures::sync::oneshot;
 use node_api::Api;
 use node_primitives::{Block, Header};
 use transaction_pool::TransactionPool;
@@ -33,19 +35,70 @@ use transaction_pool::TransactionPool;
 use tokio::executor::current_thread::TaskExecutor as LocalThreadHandle;
 use tokio::runtime::TaskExecutor as ThreadPoolHandle;
 use tokio::runtime::current_thread::Runtime as LocalRuntime;
-use tokio::timer::Interval;
+use tokio::timer::{Delay, Interval};
 
-use super::{Network, ProposerFactory};
+use super::{Network, ProposerFactory, DEFAULT_MAX_OFFLINE_REPORTS, DEFAULT_EVALUATE_DEADLINE, DEFAULT_EVALUATE_RETRIES,
+	DEFAULT_MAX_VOTE_DELAY, DEFAULT_SKEW_TOLERANCE};
 use error;
 
 const TIMER_DELAY_MS: u64 = 5000;
 const TIMER_INTERVAL_MS: u64 = 500;
+const DRAIN_INTERVAL_MS: u64 = 50;
+
+/// How long to wait for in-progress agreements when shutting down.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Counts BFT agreements in progress, so that they can be drained on shutdown.
+#[derive(Clone, Default)]
+struct InFlight(Arc<AtomicUsize>);
+
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl Drop for InFlightGuard {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::SeqCst);
+	}
+}
+
+impl InFlight {
+	/// Track the given work until it completes or is dropped.
+	fn track<F: Future<Item=(), Error=()>>(&self, work: F) -> impl Future<Item=(), Error=()> {
+		self.0.fetch_add(1, Ordering::SeqCst);
+		let guard = InFlightGuard(self.0.clone());
+		work.then(move |res| {
+			drop(guard);
+			res
+		})
+	}
+
+	/// Number of tracked pieces of work in progress.
+	fn count(&self) -> usize {
+		self.0.load(Ordering::SeqCst)
+	}
+
+	/// A future which resolves once no work is in progress or the timeout has elapsed.
+	fn drained(&self, timeout: Duration) -> impl Future<Item=(), Error=()> {
+		let this = self.clone();
+		let idle = Interval::new(Instant::now(), Duration::from_millis(DRAIN_INTERVAL_MS))
+			.map_err(|e| debug!(target: "bft", "Timer error: {:?}", e))
+			.skip_while(move |_| Ok(this.count() > 0))
+			.into_future()
+			.map(|_| ())
+			.map_err(|_| ());
+
+		let timeout = Delay::new(Instant::now() + timeout)
+			.map_err(|e| debug!(target: "bft", "Timer error: {:?}", e));
+
+		idle.select(timeout).map(|_| ()).map_err(|_| ())
+	}
+}
 
 // spin up an instance of BFT agreement on the current thread's executor.
 // panics if there is no current thread executor.
 fn start_bft<F, C>(
 	header: Header,
 	bft_service: Arc<BftService<Block, F, C>>,
+	in_flight: &InFlight,
 ) where
 	F: bft::Environment<Block> + 'static,
 	C: bft::BlockImport<Block> + bft::Authorities<Block> + 'static,
@@ -55,7 +108,7 @@ fn start_bft<F, C>(
 {
 	let mut handle = LocalThreadHandle::current();
 	match bft_service.build_upon(&header) {
-		Ok(Some(bft_work)) => if let Err(e) = handle.spawn_local(Box::new(bft_work)) {
+		Ok(Some(bft_work)) => if let Err(e) = handle.spawn_local(Box::new(in_flight.track(bft_work))) {
 		    warn!(target: "bft", "Couldn't initialize BFT agreement: {:?}", e);
 		}
 		Ok(None) => trace!(target: "bft", "Could not start agreement on top of {}", header.hash()),
@@ -67,10 +120,12 @@ fn start_bft<F, C>(
 pub struct Service {
 	thread: Option<thread::JoinHandle<()>>,
 	exit_signal: Option<::exit_future::Signal>,
+	drained: Option<oneshot::Receiver<()>>,
 }
 
 impl Service {
-	/// Create and start a new instance.
+	/// Create and start a new instance, with the first round of each agreement
+	/// timing out after `round_timeout`.
 	pub fn new<A, C, N>(
 		client: Arc<C>,
 		api: Arc<A>,
@@ -78,6 +133,7 @@ impl Service {
 		transaction_pool: Arc<TransactionPool<A>>,
 		thread_pool: ThreadPoolHandle,
 		key: ed25519::Pair,
+		round_timeout: Duration,
 	) -> Service
 		where
 			A: Api + Send + Sync + 'static,
@@ -85,10 +141,11 @@ impl Service {
 			C: bft::BlockImport<Block> + bft::Authorities<Block> + Send + Sync + 'static,
 			N: Network + Send + 'static,
 	{
-		use parking_lot::RwLock;
-		use super::OfflineTracker;
+		use parking_lot::Mutex;
+		use super::{OfflineTracker, SharedOfflineTracker};
 
 		let (signal, exit) = ::exit_future::signal();
+		let (drained_tx, drained) = oneshot::channel();
 		let thread = thread::spawn(move || {
 			let mut runtime = LocalRuntime::new().expect("Could not create local runtime");
 			let key = Arc::new(key);
@@ -98,17 +155,36 @@ impl Service {
 				transaction_pool: transaction_pool.clone(),
 				network,
 				handle: thread_pool.clone(),
-				offline: Arc::new(RwLock::new(OfflineTracker::new())),
+				offline: SharedOfflineTracker::new(OfflineTracker::new()),
+				report_offline: true,
+				max_offline_reports: DEFAULT_MAX_OFFLINE_REPORTS,
+				min_empty_interval: None,
+				last_proposal: Arc::new(Mutex::new(Instant::now())),
+				proposing: Default::default(),
+				evaluate_deadline: DEFAULT_EVALUATE_DEADLINE,
+				evaluate_retries: DEFAULT_EVALUATE_RETRIES,
+				evaluation_limit: Default::default(),
+				max_vote_delay: DEFAULT_MAX_VOTE_DELAY,
+				skew_tolerance: DEFAULT_SKEW_TOLERANCE,
+				inherent_provider: None,
+				inherent_validator: None,
+				inclusion_policy: None,
+				inclusion_mode: Default::default(),
+				max_extrinsics: None,
+				round_observer: None,
+				validator_cache: Default::default(),
 			};
-			let bft_service = Arc::new(BftService::new(client.clone(), key, factory));
+			let bft_service = Arc::new(BftService::new(client.clone(), key, factory).with_base_round_timeout(round_timeout));
+			let in_flight = InFlight::default();
 
 			let notifications = {
 				let client = client.clone();
 				let bft_service = bft_service.clone();
+				let in_flight = in_flight.clone();
 
 				client.import_notification_stream().for_each(move |notification| {
 					if notification.is_new_best {
-						start_bft(notification.header, bft_service.clone());
+						start_bft(notification.header, bft_service.clone(), &in_flight);
 					}
 					Ok(())
 				})
@@ -130,6 +206,7 @@ impl Service {
 			let timed = {
 				let c = client.clone();
 				let s = bft_service.clone();
+				let in_flight = in_flight.clone();
 
 				interval.map_err(|e| debug!(target: "bft", "Timer error: {:?}", e)).for_each(move |_| {
 					if let Ok(best_block) = c.best_block_header() {
@@ -137,7 +214,7 @@ impl Service {
 
 						if hash == prev_best {
 							debug!(target: "bft", "Starting consensus round after a timeout");
-							start_bft(best_block, s.clone());
+							start_bft(best_block, s.clone(), &in_flight);
 						}
 						prev_best = hash;
 					}
@@ -145,18 +222,44 @@ impl Service {
 				})
 			};
 
-			runtime.spawn(notifications);
-			runtime.spawn(timed);
+			// stop starting new agreements once we are asked to exit.
+			runtime.spawn(notifications.select(exit.clone()).then(|_| Ok::<(), ()>(())));
+			runtime.spawn(timed.select(exit.clone()).then(|_| Ok::<(), ()>(())));
 
 			if let Err(e) = runtime.block_on(exit) {
 				debug!("BFT event loop error {:?}", e);
 			}
+
+			if in_flight.count() > 0 {
+				debug!(target: "bft", "Waiting for {} agreements to finish", in_flight.count());
+			}
+			if let Err(e) = runtime.block_on(in_flight.drained(SHUTDOWN_TIMEOUT)) {
+				debug!("BFT event loop error {:?}", e);
+			}
+			let _ = drained_tx.send(());
 		});
 		Service {
 			thread: Some(thread),
 			exit_signal: Some(signal),
+			drained: Some(drained),
 		}
 	}
+
+	/// Signal the service to exit.
+	///
+	/// The returned future resolves once the agreements in progress have finished,
+	/// or `SHUTDOWN_TIMEOUT` has elapsed.
+	pub fn shutdown(mut self) -> impl Future<Item=(), Error=()> {
+		if let Some(signal) = self.exit_signal.take() {
+			signal.fire();
+		}
+
+		// the thread winds down on its own; don't block on joining it.
+		self.thread.take();
+		self.drained.take()
+			.expect("only taken on shutdown, which consumes the service; qed")
+			.map_err(|_| ())
+	}
 }
 
 impl Drop for Service {
@@ -170,3 +273,40 @@ impl Drop for Service {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::future;
+
+	#[test]
+	fn drain_waits_for_agreements_in_progress() {
+		let mut runtime = LocalRuntime::new().unwrap();
+		let in_flight = InFlight::default();
+		let (finish, finished) = oneshot::channel::<()>();
+		runtime.spawn(in_flight.track(finished.map_err(|_| ())));
+		assert_eq!(in_flight.count(), 1);
+
+		let finish = Delay::new(Instant::now() + Duration::from_millis(100))
+			.map(move |_| { let _ = finish.send(()); })
+			.map_err(|_| ());
+		runtime.spawn(finish);
+
+		let start = Instant::now();
+		runtime.block_on(in_flight.drained(Duration::from_secs(10))).unwrap();
+
+		assert_eq!(in_flight.count(), 0);
+		assert!(start.elapsed() < Duration::from_secs(10));
+	}
+
+	#[test]
+	fn drain_times_out_on_stuck_agreements() {
+		let mut runtime = LocalRuntime::new().unwrap();
+		let in_flight = InFlight::default();
+		runtime.spawn(in_flight.track(future::empty::<(), ()>()));
+
+		runtime.block_on(in_flight.drained(Duration::from_millis(100))).unwrap();
+
+		assert_eq!(in_flight.count(), 1);
+	}
+}
@@ -0,0 +1,145 @@
+// Copyright 2018 Parity Technologies (UK) Ltd.
+// This file is part of Substrate.
+
+// Substrate is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Substrate is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Substrate.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Helpers for driving consensus without a real network.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use bft;
+use futures::prelude::*;
+use futures::sync::mpsc;
+use node_primitives::{Block, Hash, SessionKey};
+use parking_lot::Mutex;
+use tokio::runtime::TaskExecutor;
+
+use super::{Error, Network};
+
+/// A network which routes every message straight back to the local node.
+///
+/// Useful for running single-authority consensus in tests.
+#[derive(Clone, Copy, Default)]
+pub struct LoopbackNetwork;
+
+impl Network for LoopbackNetwork {
+	type Input = LoopbackInput;
+	type Output = LoopbackOutput;
+
+	fn communication_for(
+		&self,
+		_validators: &[SessionKey],
+		_local_id: SessionKey,
+		_parent_hash: Hash,
+		_task_executor: TaskExecutor,
+	) -> (Self::Input, Self::Output) {
+		let (tx, rx) = mpsc::unbounded();
+		(LoopbackInput(rx), LoopbackOutput(tx))
+	}
+}
+
+/// Messages echoed back by a `LoopbackNetwork`.
+pub struct LoopbackInput(mpsc::UnboundedReceiver<bft::Communication<Block>>);
+
+impl Stream for LoopbackInput {
+	type Item = bft::Communication<Block>;
+	type Error = Error;
+
+	fn poll(&mut self) -> Poll<Option<Self::Item>, Error> {
+		Ok(self.0.poll().expect("unbounded receivers never fail; qed"))
+	}
+}
+
+/// Messages sent into a `LoopbackNetwork`.
+pub struct LoopbackOutput(mpsc::UnboundedSender<bft::Communication<Block>>);
+
+impl Sink for LoopbackOutput {
+	type SinkItem = bft::Communication<Block>;
+	type SinkError = Error;
+
+	fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Error> {
+		// nobody is listening anymore if this fails.
+		let _ = self.0.unbounded_send(item);
+		Ok(AsyncSink::Ready)
+	}
+
+	fn poll_complete(&mut self) -> Poll<(), Error> {
+		Ok(Async::Ready(()))
+	}
+}
+
+// Messages sent so far and the members listening, for a single parent.
+#[derive(Default)]
+struct Topic {
+	sent: Vec<bft::Communication<Block>>,
+	members: Vec<mpsc::UnboundedSender<bft::Communication<Block>>>,
+}
+
+/// A network connecting any number of local nodes through a shared in-memory bus.
+///
+/// Every message is routed to all nodes, the sender included, communicating on top of
+/// the same parent. Nodes joining late are sent what they missed first. Clones share
+/// the bus, so give one to each node's proposer factory.
+#[derive(Clone, Default)]
+pub struct BusNetwork(Arc<Mutex<HashMap<Hash, Topic>>>);
+
+impl Network for BusNetwork {
+	type Input = LoopbackInput;
+	type Output = BusOutput;
+
+	fn communication_for(
+		&self,
+		_validators: &[SessionKey],
+		_local_id: SessionKey,
+		parent_hash: Hash,
+		_task_executor: TaskExecutor,
+	) -> (Self::Input, Self::Output) {
+		let (tx, rx) = mpsc::unbounded();
+		{
+			let mut bus = self.0.lock();
+			let topic = bus.entry(parent_hash).or_insert_with(Topic::default);
+			for message in &topic.sent {
+				let _ = tx.unbounded_send(message.clone());
+			}
+			topic.members.push(tx);
+		}
+
+		(LoopbackInput(rx), BusOutput { bus: self.clone(), parent_hash })
+	}
+}
+
+/// Messages sent onto a `BusNetwork`.
+pub struct BusOutput {
+	bus: BusNetwork,
+	parent_hash: Hash,
+}
+
+impl Sink for BusOutput {
+	type SinkItem = bft::Communication<Block>;
+	type SinkError = Error;
+
+	fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Error> {
+		let mut bus = (self.bus).0.lock();
+		let topic = bus.entry(self.parent_hash).or_insert_with(Topic::default);
+		// members which went away are not listening anymore.
+		topic.members.retain(|member| member.unbounded_send(item.clone()).is_ok());
+		topic.sent.push(item);
+		Ok(AsyncSink::Ready)
+	}
+
+	fn poll_complete(&mut self) -> Poll<(), Error> {
+		Ok(Async::Ready(()))
+	}
+}
@@ -79,13 +79,36 @@ pub type Block = generic::Block<Header, UncheckedExtrinsic>;
 /// Block ID.
 pub type BlockId = generic::BlockId<Block>;
 
+/// Limits on the encoded size of transactions, shared by block authoring and the
+/// transaction pool so the two can't drift apart.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct SizeLimits {
+	/// Maximal total size of the transactions in a block.
+	pub max_block_size: usize,
+	/// Maximal size of a single transaction.
+	pub max_transaction_size: usize,
+}
+
+/// The size limits used by the node.
+pub const SIZE_LIMITS: SizeLimits = SizeLimits {
+	max_block_size: 4 * 1024 * 1024,
+	max_transaction_size: 4 * 1024 * 1024,
+};
+
+impl Default for SizeLimits {
+	fn default() -> Self {
+		SIZE_LIMITS
+	}
+}
+
 /// Opaque, encoded, unchecked extrinsic.
 #[derive(PartialEq, Eq, Clone, Default, Encode, Decode)]
 #[cfg_attr(feature = "std", derive(Serialize, Deserialize, Debug))]
 pub struct UncheckedExtrinsic(#[cfg_attr(feature = "std", serde(with="bytes"))] pub Vec<u8>);
 ///
 /// Inherent data to include in a block.
-#[derive(Encode, Decode)]
+#[derive(Clone, Encode, Decode)]
 pub struct InherentData {
 	/// Current timestamp.
 	pub timestamp: Timestamp,
@@ -83,15 +83,19 @@ const NOTE_OFFLINE_POSITION: u32 = 1;
 
 const INHERENT: ApiId = *b"inherent";
 const VALIDATX: ApiId = *b"validatx";
+const CONSCTXT: ApiId = *b"consctxt";
+
+/// Maximum encoded length of the transactions in a block.
+pub const MAX_BLOCK_LENGTH: u32 = node_primitives::SIZE_LIMITS.max_block_size as u32;
 
 /// Runtime version.
 pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: ver_str!("node"),
 	impl_name: ver_str!("substrate-node"),
 	authoring_version: 1,
-	spec_version: 1,
+	spec_version: 3,
 	impl_version: 0,
-	apis: apis_vec!([(INHERENT, 1), (VALIDATX, 1)]),
+	apis: apis_vec!([(INHERENT, 1), (VALIDATX, 1), (CONSCTXT, 1)]),
 };
 
 impl system::Trait for Runtime {
@@ -241,9 +245,14 @@ pub mod api {
 		inherent_extrinsics => |inherent| super::inherent_extrinsics(inherent),
 		validator_count => |()| super::Session::validator_count(),
 		validators => |()| super::Session::validators(),
+		session_index => |()| super::Session::current_index(),
+		max_block_length => |()| super::MAX_BLOCK_LENGTH,
 		timestamp => |()| super::Timestamp::get(),
 		random_seed => |()| super::System::random_seed(),
+		consensus_context => |()| (super::System::random_seed(), super::Session::validators()),
 		account_nonce => |account| super::System::account_nonce(&account),
+		free_balance => |account| super::Balances::free_balance(&account),
+		stake_of => |account| super::Staking::slashable_balance(&account),
 		lookup_address => |address| super::Balances::lookup_address(address),
 		validate_transaction => |tx| super::Executive::validate_transaction(tx)
 	);
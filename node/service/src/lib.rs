@@ -183,6 +183,7 @@ pub fn new_full(config: Configuration, executor: TaskExecutor)
 			service.transaction_pool(),
 			executor,
 			key,
+			consensus::DEFAULT_ROUND_TIMEOUT,
 		))
 	} else {
 		None
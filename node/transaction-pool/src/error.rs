@@ -16,15 +16,24 @@
 
 use transaction_pool;
 use node_api;
-use primitives::Hash;
+use primitives::{AccountId, Balance, BlockNumber, Hash};
 use runtime::{Address, UncheckedExtrinsic};
 
 error_chain! {
 	links {
-		Pool(transaction_pool::Error, transaction_pool::ErrorKind);
 		Api(node_api::Error, node_api::ErrorKind);
 	}
 	errors {
+		/// Error reported by the underlying pool.
+		Pool(kind: transaction_pool::ErrorKind) {
+			description("Transaction pool error"),
+			display("{}", kind),
+		}
+		/// The pool is at capacity and the extrinsic doesn't outrank any it holds.
+		PoolFull(hash: String) {
+			description("Transaction pool is full"),
+			display("Transaction pool is full; {} was not imported", hash),
+		}
 		/// Unexpected extrinsic format submitted
 		InvalidExtrinsicFormat {
 			description("Invalid extrinsic format."),
@@ -60,6 +69,52 @@ error_chain! {
 			description("Extrinsic too large"),
 			display("Extrinsic is too large ({} > {})", got, max),
 		}
+		/// The transaction pays less than the minimum fee.
+		FeeTooLow(fee: Balance, min: Balance) {
+			description("Transaction fee below the minimum"),
+			display("Transaction fee {} is below the minimum of {}", fee, min),
+		}
+		/// The transaction remains valid for longer than the pool accepts.
+		MortalityTooLong(lifetime: BlockNumber, horizon: BlockNumber) {
+			description("Transaction is valid for too long"),
+			display("Transaction is valid for {} more blocks, beyond the horizon of {}", lifetime, horizon),
+		}
+		/// The sender has no on-chain presence and new accounts aren't accepted.
+		UnknownAccount(who: AccountId) {
+			description("Transaction from an unknown account"),
+			display("Account {:?} has no on-chain presence", who),
+		}
+		/// The checks of the transaction didn't complete, e.g. because they panicked.
+		VerificationAborted {
+			description("Transaction verification aborted"),
+			display("Verification of the transaction was aborted"),
+		}
+		/// The transaction was found to be invalid recently and is banned for a while.
+		BannedAsInvalid(hash: Hash) {
+			description("Transaction temporarily banned after being found invalid"),
+			display("Transaction {:?} is temporarily banned after being found invalid", hash),
+		}
+		/// Sender can't afford the transaction fee.
+		InsufficientBalance(fee: Balance, balance: Balance) {
+			description("Sender can't afford the transaction fee"),
+			display("Sender balance {} is insufficient to pay the fee of {}", balance, fee),
+		}
+	}
+}
+
+impl From<transaction_pool::BannedAsInvalid<Hash>> for Error {
+	fn from(e: transaction_pool::BannedAsInvalid<Hash>) -> Self {
+		ErrorKind::BannedAsInvalid(e.0).into()
+	}
+}
+
+impl From<transaction_pool::Error> for Error {
+	fn from(e: transaction_pool::Error) -> Self {
+		match e {
+			transaction_pool::Error(transaction_pool::ErrorKind::TooCheapToEnter(hash, _), state) =>
+				Error(ErrorKind::PoolFull(hash), state),
+			transaction_pool::Error(kind, state) => Error(ErrorKind::Pool(kind), state),
+		}
 	}
 }
 
@@ -36,23 +36,77 @@ extern crate log;
 mod error;
 
 use std::{
-	cmp::Ordering,
-	collections::HashMap,
-	sync::Arc,
+	cmp::{self, Ordering},
+	collections::{HashMap, VecDeque},
+	sync::{mpsc, Arc},
+	thread,
 };
 
 use codec::{Decode, Encode};
+use parking_lot::Mutex;
 use transaction_pool::{Readiness, scoring::{Change, Choice}, VerifiedFor, ExtrinsicFor};
 use node_api::Api;
-use primitives::{AccountId, BlockId, Block, Hash, Index, BlockNumber};
+use primitives::{AccountId, Balance, BlockId, Block, Hash, Index, BlockNumber};
 use runtime::{Address, UncheckedExtrinsic};
+use sr_primitives::generic;
 use sr_primitives::traits::{Bounded, Checkable, Hash as HashT, BlakeTwo256, Lookup, CurrentHeight, BlockNumberToHash};
 
-pub use transaction_pool::{Options, Status, LightStatus, VerifiedTransaction as VerifiedTransactionOps};
+pub use transaction_pool::{Options, Status, LightStatus, PartitionedStatus, RemovalReason, VerifiedTransaction as VerifiedTransactionOps};
 pub use error::{Error, ErrorKind, Result};
 
 /// Maximal size of a single encoded extrinsic.
-const MAX_TRANSACTION_SIZE: usize = 4 * 1024 * 1024;
+const MAX_TRANSACTION_SIZE: usize = primitives::SIZE_LIMITS.max_transaction_size;
+
+/// Maximal number of checked extrinsics remembered.
+const VERIFICATION_CACHE_SIZE: usize = 1024;
+
+/// Default number of threads verifying a batch of extrinsics.
+pub const DEFAULT_VERIFICATION_THREADS: usize = 4;
+
+type VerificationJob = Box<FnMut() + Send>;
+
+/// Threads checking batches of extrinsics for `ChainApi::verify_many`. Clones share
+/// the same threads, so batches beyond their number queue for a free one.
+#[derive(Clone)]
+pub struct VerificationPool {
+	threads: usize,
+	jobs: Arc<Mutex<mpsc::Sender<VerificationJob>>>,
+}
+
+impl VerificationPool {
+	/// Check extrinsics on up to `threads` threads at once.
+	pub fn new(threads: usize) -> Self {
+		let threads = cmp::max(threads, 1);
+		let (sender, receiver) = mpsc::channel::<VerificationJob>();
+		let receiver = Arc::new(Mutex::new(receiver));
+		for _ in 0..threads {
+			let receiver = receiver.clone();
+			// the threads exit once the pool and all its clones are dropped.
+			thread::spawn(move || loop {
+				let job = receiver.lock().recv();
+				match job {
+					Ok(mut job) => {
+						// a panicking check fails its batch instead.
+						let _ = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| job()));
+					}
+					Err(_) => break,
+				}
+			});
+		}
+
+		VerificationPool {
+			threads,
+			jobs: Arc::new(Mutex::new(sender)),
+		}
+	}
+
+	// Queue `f` to be executed once a thread is free.
+	fn execute<F: FnOnce() + Send + 'static>(&self, f: F) {
+		let mut f = Some(f);
+		let job: VerificationJob = Box::new(move || if let Some(f) = f.take() { f() });
+		let _ = self.jobs.lock().send(job);
+	}
+}
 
 /// Type alias for the transaction pool.
 pub type TransactionPool<A> = transaction_pool::Pool<ChainApi<A>>;
@@ -66,7 +120,13 @@ pub struct VerifiedTransaction {
 	pub sender: AccountId,
 	/// Transaction index.
 	pub index: Index,
+	/// First block number at which the transaction's era has ended.
+	pub era_end: BlockNumber,
 	encoded_size: usize,
+	fee: Balance,
+	// the policy of the `ChainApi` which verified the transaction, since choosing
+	// between transactions has no access to it.
+	replacement: ReplacementPolicy,
 }
 
 impl VerifiedTransaction {
@@ -103,9 +163,95 @@ impl transaction_pool::VerifiedTransaction for VerifiedTransaction {
 	}
 }
 
+/// How to choose between two transactions of a sender with the same index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplacementPolicy {
+	/// The transaction which arrived last replaces the other.
+	PreferNew,
+	/// The transaction which arrived first is kept, resisting replacement spam.
+	PreferOld,
+	/// The transaction paying the higher fee given by the `FeeSchedule` is kept, or the
+	/// first one on a tie. Without a schedule every fee is 0, so this behaves like `PreferOld`.
+	PreferHigherFee,
+}
+
+impl Default for ReplacementPolicy {
+	fn default() -> Self {
+		ReplacementPolicy::PreferNew
+	}
+}
+
+/// Transaction fees, used to check that senders can afford their transactions.
+#[derive(Clone, Copy, Debug)]
+pub struct FeeSchedule {
+	/// Fee paid for every transaction.
+	pub base_fee: Balance,
+	/// Fee paid for every byte of an encoded transaction.
+	pub byte_fee: Balance,
+}
+
+impl FeeSchedule {
+	/// Fee for a transaction of given encoded size.
+	pub fn fee(&self, encoded_size: usize) -> Balance {
+		self.base_fee.saturating_add(self.byte_fee.saturating_mul(encoded_size as Balance))
+	}
+}
+
+// Senders and indices of recently checked extrinsics, so that resubmitted
+// extrinsics don't have their signatures checked again. Entries are only
+// valid for the era checkpoint they were checked against.
+#[derive(Default)]
+struct VerificationCache {
+	checked: HashMap<Hash, ((AccountId, Index), Option<Hash>)>,
+	order: VecDeque<Hash>,
+}
+
+impl VerificationCache {
+	fn get(&mut self, hash: &Hash, checkpoint: Option<Hash>) -> Option<(AccountId, Index)> {
+		let checked = match self.checked.get(hash) {
+			Some(&(checked, cached_checkpoint)) if cached_checkpoint == checkpoint => checked,
+			_ => return None,
+		};
+
+		self.order.retain(|h| h != hash);
+		self.order.push_back(*hash);
+		Some(checked)
+	}
+
+	fn insert(&mut self, hash: Hash, checked: (AccountId, Index), checkpoint: Option<Hash>) {
+		if self.checked.insert(hash, (checked, checkpoint)).is_some() {
+			self.order.retain(|h| *h != hash);
+		}
+		self.order.push_back(hash);
+
+		while self.order.len() > VERIFICATION_CACHE_SIZE {
+			if let Some(oldest) = self.order.pop_front() {
+				self.checked.remove(&oldest);
+			}
+		}
+	}
+}
+
+// Next indices of senders as of the block they were fetched at.
+#[derive(Default)]
+struct NonceCache {
+	at: Option<BlockId>,
+	next: HashMap<AccountId, Index>,
+}
+
 /// The transaction pool logic.
 pub struct ChainApi<A> {
 	api: Arc<A>,
+	fees: Option<FeeSchedule>,
+	verified: Arc<Mutex<VerificationCache>>,
+	log_payloads: bool,
+	verification_pool: Option<VerificationPool>,
+	min_fee: Balance,
+	mortality_horizon: Option<BlockNumber>,
+	allow_new_accounts: bool,
+	// next indices of senders kept across readiness checks, if enabled.
+	nonce_cache: Option<Arc<Mutex<NonceCache>>>,
+	replacement: ReplacementPolicy,
 }
 
 impl<A> ChainApi<A> where
@@ -115,6 +261,200 @@ impl<A> ChainApi<A> where
 	pub fn new(api: Arc<A>) -> Self {
 		ChainApi {
 			api,
+			fees: None,
+			verified: Default::default(),
+			log_payloads: true,
+			verification_pool: Some(VerificationPool::new(DEFAULT_VERIFICATION_THREADS)),
+			min_fee: 0,
+			mortality_horizon: None,
+			allow_new_accounts: true,
+			nonce_cache: None,
+			replacement: Default::default(),
+		}
+	}
+
+	/// Create a new instance which rejects transactions whose senders can't pay
+	/// the fees given by the schedule.
+	pub fn with_fees(api: Arc<A>, fees: FeeSchedule) -> Self {
+		ChainApi { fees: Some(fees), ..ChainApi::new(api) }
+	}
+
+	/// Set whether the contents of submitted transactions are logged, rather than
+	/// just their hash and size. Enabled by default.
+	pub fn log_payloads(mut self, log_payloads: bool) -> Self {
+		self.log_payloads = log_payloads;
+		self
+	}
+
+	/// Reject transactions paying less than `min_fee`. Transactions pay the fee of the
+	/// schedule for their size, or nothing without one.
+	pub fn min_fee(mut self, min_fee: Balance) -> Self {
+		self.min_fee = min_fee;
+		self
+	}
+
+	/// Reject transactions which remain valid for more than `blocks` blocks from the
+	/// current height, including immortal ones. Disabled by default.
+	pub fn mortality_horizon(mut self, blocks: BlockNumber) -> Self {
+		self.mortality_horizon = Some(blocks);
+		self
+	}
+
+	/// Set whether transactions from accounts without on-chain presence are accepted,
+	/// starting at index 0. Enabled by default.
+	pub fn allow_new_accounts(mut self, allow: bool) -> Self {
+		self.allow_new_accounts = allow;
+		self
+	}
+
+	/// Set how to choose between two transactions of a sender with the same index.
+	/// The new one replaces the old one by default.
+	///
+	/// The policy is recorded on every transaction verified by this instance, and the
+	/// one of the newer transaction applies.
+	pub fn replacement_policy(mut self, policy: ReplacementPolicy) -> Self {
+		self.replacement = policy;
+		self
+	}
+
+	/// Keep the next indices of senders fetched while checking readiness at a block,
+	/// rather than fetching them again on every check. The cache is emptied whenever
+	/// readiness is checked at another block. Use `TransactionPool::api` to reach
+	/// `invalidate_sender` and `clear_nonce_cache` once the pool is built.
+	pub fn with_nonce_cache(mut self) -> Self {
+		self.nonce_cache = Some(Default::default());
+		self
+	}
+
+	/// Get the cached next index of a sender at given block, if any.
+	pub fn cached_nonce(&self, at: &BlockId, sender: &AccountId) -> Option<Index> {
+		self.nonce_cache.as_ref().and_then(|cache| {
+			let cache = cache.lock();
+			if cache.at.as_ref() == Some(at) {
+				cache.next.get(sender).cloned()
+			} else {
+				None
+			}
+		})
+	}
+
+	/// Forget the cached next index of a sender, so it is fetched again.
+	pub fn invalidate_sender(&self, sender: &AccountId) {
+		if let Some(ref cache) = self.nonce_cache {
+			cache.lock().next.remove(sender);
+		}
+	}
+
+	/// Forget all cached next indices.
+	pub fn clear_nonce_cache(&self) {
+		if let Some(ref cache) = self.nonce_cache {
+			cache.lock().next.clear();
+		}
+	}
+
+	/// Set the maximal number of threads `verify_many` spreads the checks over.
+	/// A single thread checks batches on the calling thread.
+	pub fn verification_threads(mut self, threads: usize) -> Self {
+		self.verification_pool = if threads > 1 { Some(VerificationPool::new(threads)) } else { None };
+		self
+	}
+
+	/// Spread the checks of `verify_many` over the threads of a pool which may be
+	/// shared with other instances.
+	pub fn verification_pool(mut self, pool: VerificationPool) -> Self {
+		self.verification_pool = Some(pool);
+		self
+	}
+
+	/// Verify a batch of extrinsics at the given block, checking them in parallel.
+	///
+	/// The results are in the same order as `xts`. Extrinsics of a batch whose checks
+	/// panicked fail with `ErrorKind::VerificationAborted`.
+	pub fn verify_many(&self, at: &BlockId, xts: Vec<primitives::UncheckedExtrinsic>) -> Vec<Result<VerifiedTransaction>> where
+		A: Send + Sync + 'static,
+	{
+		use transaction_pool::ChainApi as ChainApiT;
+
+		let pool = match self.verification_pool {
+			Some(ref pool) if pool.threads > 1 && xts.len() > 1 => pool,
+			_ => return xts.iter().map(|xt| self.verify_transaction(at, xt)).collect(),
+		};
+
+		// contiguous chunks, so collecting the batches in order keeps the results in order.
+		let threads = cmp::min(pool.threads, xts.len());
+		let chunk_size = (xts.len() + threads - 1) / threads;
+		let batches: Vec<_> = xts.chunks(chunk_size).map(|chunk| {
+			let (sender, receiver) = mpsc::channel();
+			let api = self.share();
+			let at = at.clone();
+			let chunk = chunk.to_vec();
+			let len = chunk.len();
+			pool.execute(move || {
+				let _ = sender.send(chunk.iter().map(|xt| api.verify_transaction(&at, xt)).collect::<Vec<_>>());
+			});
+			(receiver, len)
+		}).collect();
+
+		batches.into_iter()
+			.flat_map(|(receiver, len)| receiver.recv().unwrap_or_else(|_| {
+				(0..len).map(|_| Err(ErrorKind::VerificationAborted.into())).collect()
+			}))
+			.collect()
+	}
+
+	// A handle sharing the chain and verification cache of this one, checking on the calling thread.
+	fn share(&self) -> Self {
+		ChainApi {
+			api: self.api.clone(),
+			verified: self.verified.clone(),
+			verification_pool: None,
+			nonce_cache: self.nonce_cache.clone(),
+			..*self
+		}
+	}
+
+	/// Create a readiness context with the next indices of `senders` at the given
+	/// block fetched up front, so checking their transactions needs no further lookups.
+	pub fn ready_at(&self, at: &BlockId, senders: &[AccountId]) -> HashMap<AccountId, Index> {
+		senders.iter()
+			.map(|&sender| (sender, self.next_index(at, sender)))
+			.collect()
+	}
+
+	fn next_index(&self, at: &BlockId, sender: AccountId) -> Index {
+		if let Some(ref cache) = self.nonce_cache {
+			let mut cache = cache.lock();
+			if cache.at.as_ref() != Some(at) {
+				cache.at = Some(at.clone());
+				cache.next.clear();
+			}
+			if let Some(&index) = cache.next.get(&sender) {
+				return index;
+			}
+		}
+
+		// TODO: find a way to handle index error properly -- will need changes to
+		// transaction-pool trait.
+		let index = match self.api.index(at, sender) {
+			Ok(index) => index,
+			Err(_) => return Bounded::max_value(),
+		};
+		if let Some(ref cache) = self.nonce_cache {
+			let mut cache = cache.lock();
+			// readiness may have been checked at another block meanwhile.
+			if cache.at.as_ref() == Some(at) {
+				cache.next.insert(sender, index);
+			}
+		}
+		index
+	}
+
+	fn block_number(&self, at: &BlockId) -> BlockNumber {
+		match *at {
+			generic::BlockId::Number(n) => n,
+			// TODO: resolve the number of arbitrary blocks; the pool is only
+			// queried at the best block for now.
+			generic::BlockId::Hash(_) => self.api.current_height(),
 		}
 	}
 }
@@ -123,6 +463,12 @@ impl<A> ChainApi<A> where
 ///
 /// This is due for removal when #721 lands
 pub struct LocalContext<'a, A: 'a>(&'a Arc<A>);
+impl<'a, A: 'a + Api> LocalContext<'a, A> {
+	/// Hash of the genesis block, which immortal transactions are anchored to.
+	pub fn genesis_hash(&self) -> Result<Hash> {
+		Ok(self.0.genesis_block_hash()?)
+	}
+}
 impl<'a, A: 'a + Api> CurrentHeight for LocalContext<'a, A> {
 	type BlockNumber = BlockNumber;
 	fn current_height(&self) -> BlockNumber {
@@ -140,7 +486,10 @@ impl<'a, A: 'a + Api> Lookup for LocalContext<'a, A> {
 	type Source = Address;
 	type Target = AccountId;
 	fn lookup(&self, a: Address) -> ::std::result::Result<AccountId, &'static str> {
-		self.0.lookup(&BlockId::number(self.current_height()), a).unwrap_or(None).ok_or("error with lookup")
+		self.0.best_block_id()
+			.and_then(|at| self.0.lookup(&at, a))
+			.unwrap_or(None)
+			.ok_or("error with lookup")
 	}
 }
 
@@ -156,7 +505,7 @@ impl<A> transaction_pool::ChainApi for ChainApi<A> where
 	type Score = u64;
 	type Event = ();
 
-	fn verify_transaction(&self, _at: &BlockId, xt: &ExtrinsicFor<Self>) -> Result<Self::VEx> {
+	fn verify_transaction(&self, at: &BlockId, xt: &ExtrinsicFor<Self>) -> Result<Self::VEx> {
 		let encoded = xt.encode();
 		let uxt = UncheckedExtrinsic::decode(&mut encoded.as_slice()).ok_or_else(|| ErrorKind::InvalidExtrinsicFormat)?;
 		if !uxt.is_signed() {
@@ -168,12 +517,56 @@ impl<A> transaction_pool::ChainApi for ChainApi<A> where
 			bail!(ErrorKind::TooLarge(encoded_size, MAX_TRANSACTION_SIZE));
 		}
 
-		debug!(target: "transaction-pool", "Transaction submitted: {}", ::substrate_primitives::hexdisplay::HexDisplay::from(&encoded));
-		let checked = uxt.clone().check(&LocalContext(&self.api))?;
-		let (sender, index) = checked.signed.expect("function previously bailed unless uxt.is_signed(); qed");
+		let fee = self.fees.map_or(0, |fees| fees.fee(encoded_size));
+		if fee < self.min_fee {
+			bail!(ErrorKind::FeeTooLow(fee, self.min_fee));
+		}
+
+		if self.log_payloads {
+			debug!(target: "transaction-pool", "Transaction submitted: {}", ::substrate_primitives::hexdisplay::HexDisplay::from(&encoded));
+		} else {
+			debug!(target: "transaction-pool", "Transaction submitted: {} ({} bytes)", hash, encoded_size);
+		}
+		let current_height = self.api.current_height();
+		let (era_birth, era_end) = uxt.signature.as_ref()
+			.map(|&(_, _, _, era)| (era.birth(current_height), era.death(current_height)))
+			.expect("function previously bailed unless uxt.is_signed(); qed");
+
+		if let Some(horizon) = self.mortality_horizon {
+			let lifetime = era_end.saturating_sub(current_height);
+			if lifetime > horizon {
+				bail!(ErrorKind::MortalityTooLong(lifetime, horizon));
+			}
+		}
 
+		// the signature covers the hash of the block the era started at, which
+		// changes with the era or on a re-org.
+		let checkpoint = self.api.block_number_to_hash(era_birth);
+		let cached = self.verified.lock().get(&hash, checkpoint);
+		let (sender, index) = match cached {
+			Some(checked) => checked,
+			None => {
+				let checked = uxt.clone().check(&LocalContext(&self.api))?;
+				let checked = checked.signed.expect("function previously bailed unless uxt.is_signed(); qed");
+				self.verified.lock().insert(hash, checked, checkpoint);
+				checked
+			}
+		};
+
+		if !self.allow_new_accounts && !self.api.account_exists(at, sender)? {
+			bail!(ErrorKind::UnknownAccount(sender));
+		}
 
-		if encoded_size < 1024 {
+		if self.fees.is_some() {
+			let balance = self.api.balance(at, sender)?;
+			if fee > balance {
+				bail!(ErrorKind::InsufficientBalance(fee, balance));
+			}
+		}
+
+		if !self.log_payloads {
+			debug!(target: "transaction-pool", "Transaction verified: {} ({} bytes)", hash, encoded_size);
+		} else if encoded_size < 1024 {
 			debug!(target: "transaction-pool", "Transaction verified: {} => {:?}", hash, uxt);
 		} else {
 			debug!(target: "transaction-pool", "Transaction verified: {} ({} bytes is too large to display)", hash, encoded_size);
@@ -183,7 +576,10 @@ impl<A> transaction_pool::ChainApi for ChainApi<A> where
 			index,
 			sender,
 			hash,
+			era_end,
 			encoded_size,
+			fee,
+			replacement: self.replacement,
 		})
 	}
 
@@ -195,11 +591,13 @@ impl<A> transaction_pool::ChainApi for ChainApi<A> where
 		let sender = xt.verified.sender().clone();
 		trace!(target: "transaction-pool", "Checking readiness of {} (from {})", xt.verified.hash, sender);
 
-		// TODO: find a way to handle index error properly -- will need changes to
-		// transaction-pool trait.
-		let api = &self.api;
+		if self.block_number(at) >= xt.verified.era_end {
+			trace!(target: "transaction-pool", "Era of {} ended at block {}", xt.verified.hash, xt.verified.era_end);
+			return Readiness::Stale;
+		}
+
 		let next_index = known_nonces.entry(sender)
-			.or_insert_with(|| api.index(at, sender).ok().unwrap_or_else(Bounded::max_value));
+			.or_insert_with(|| self.next_index(at, sender));
 
 		trace!(target: "transaction-pool", "Next index for sender is {}; xt index is {}", next_index, xt.verified.index);
 
@@ -211,7 +609,6 @@ impl<A> transaction_pool::ChainApi for ChainApi<A> where
 			// Perhaps we could mark as stale if `index - state_index` > X?
 			Ordering::Greater => Readiness::Future,
 			Ordering::Equal => Readiness::Ready,
-			// TODO [ToDr] Should mark transactions referencing too old blockhash as `Stale` as well.
 			Ordering::Less => Readiness::Stale,
 		};
 
@@ -222,14 +619,25 @@ impl<A> transaction_pool::ChainApi for ChainApi<A> where
 	}
 
 	fn compare(old: &VerifiedFor<Self>, other: &VerifiedFor<Self>) -> Ordering {
+		// break ties by sender so the order is the same on every node. transactions of
+		// a single sender with the same index stay equal; one replaces the other.
 		old.verified.index().cmp(&other.verified.index())
+			.then_with(|| old.verified.sender().cmp(other.verified.sender()))
 	}
 
 	fn choose(old: &VerifiedFor<Self>, new: &VerifiedFor<Self>) -> Choice {
-		if old.verified.index() == new.verified.index() {
-			return Choice::ReplaceOld;
+		if old.verified.index() != new.verified.index() {
+			return Choice::InsertNew;
+		}
+
+		// `choose` has no access to the pool's `ChainApi`, so the policy travels with
+		// the transactions verified under it.
+		match new.verified.replacement {
+			ReplacementPolicy::PreferNew => Choice::ReplaceOld,
+			ReplacementPolicy::PreferOld => Choice::RejectNew,
+			ReplacementPolicy::PreferHigherFee if new.verified.fee > old.verified.fee => Choice::ReplaceOld,
+			ReplacementPolicy::PreferHigherFee => Choice::RejectNew,
 		}
-		Choice::InsertNew
 	}
 
 	fn update_scores(
@@ -250,3 +658,485 @@ impl<A> transaction_pool::ChainApi for ChainApi<A> where
 	}
 }
 
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use parking_lot::Mutex;
+	use node_api::{BlockBuilder, Result as ApiResult};
+	use primitives::{InherentData, SessionKey, Timestamp, UncheckedExtrinsic as NodeExtrinsic};
+	use runtime::{Call, RawAddress, TimestampCall};
+	use sr_primitives::generic::Era;
+	use sr_primitives::transaction_validity::TransactionValidity;
+	use substrate_keyring::Keyring;
+
+	struct TestBlockBuilder;
+
+	impl BlockBuilder for TestBlockBuilder {
+		fn push_extrinsic(&mut self, _extrinsic: NodeExtrinsic) -> ApiResult<()> {
+			unimplemented!()
+		}
+
+		fn bake(self) -> ApiResult<Block> {
+			unimplemented!()
+		}
+	}
+
+	#[derive(Default)]
+	struct TestApi {
+		height: Mutex<BlockNumber>,
+		balance: Balance,
+		lookups: Mutex<Vec<BlockId>>,
+		index_calls: Mutex<usize>,
+		// balance lookups of this account panic.
+		panicking: Option<AccountId>,
+	}
+
+	impl CurrentHeight for TestApi {
+		type BlockNumber = BlockNumber;
+
+		fn current_height(&self) -> BlockNumber {
+			*self.height.lock()
+		}
+	}
+
+	impl BlockNumberToHash for TestApi {
+		type BlockNumber = BlockNumber;
+		type Hash = Hash;
+
+		fn block_number_to_hash(&self, n: BlockNumber) -> Option<Hash> {
+			if n <= self.current_height() {
+				Some(Hash::from(n + 1))
+			} else {
+				None
+			}
+		}
+	}
+
+	impl Api for TestApi {
+		type BlockBuilder = TestBlockBuilder;
+
+		fn best_block_id(&self) -> ApiResult<BlockId> {
+			Ok(BlockId::hash(self.block_number_to_hash(self.current_height()).unwrap()))
+		}
+
+		fn genesis_block_hash(&self) -> ApiResult<Hash> {
+			Ok(Hash::from(1))
+		}
+
+		fn chain_spec_name(&self, _at: &BlockId) -> ApiResult<String> {
+			Ok("test".into())
+		}
+
+		fn has_block(&self, _at: &BlockId) -> ApiResult<bool> {
+			Ok(true)
+		}
+
+		fn session_keys(&self, _at: &BlockId) -> ApiResult<Vec<SessionKey>> {
+			Ok(Vec::new())
+		}
+
+		fn validators(&self, _at: &BlockId) -> ApiResult<Vec<AccountId>> {
+			Ok(Vec::new())
+		}
+
+		fn session_index(&self, _at: &BlockId) -> ApiResult<BlockNumber> {
+			Ok(0)
+		}
+
+		fn random_seed(&self, _at: &BlockId) -> ApiResult<Hash> {
+			Ok(Default::default())
+		}
+
+		fn max_block_length(&self, _at: &BlockId) -> ApiResult<usize> {
+			Ok(4 * 1024 * 1024)
+		}
+
+		fn author_reward(&self, _at: &BlockId) -> ApiResult<Balance> {
+			Err(node_api::ErrorKind::ApiNotImplemented("author_reward").into())
+		}
+
+		fn timestamp(&self, _at: &BlockId) -> ApiResult<Timestamp> {
+			Ok(0)
+		}
+
+		fn index(&self, _at: &BlockId, _account: AccountId) -> ApiResult<Index> {
+			*self.index_calls.lock() += 1;
+			Ok(0)
+		}
+
+		fn balance(&self, _at: &BlockId, account: AccountId) -> ApiResult<Balance> {
+			if self.panicking == Some(account) {
+				panic!("balance of {} unavailable", account);
+			}
+			Ok(self.balance)
+		}
+
+		fn stake_of(&self, _at: &BlockId, _account: AccountId) -> ApiResult<Balance> {
+			Ok(0)
+		}
+
+		fn lookup(&self, at: &BlockId, address: Address) -> ApiResult<Option<AccountId>> {
+			self.lookups.lock().push(at.clone());
+			match address {
+				RawAddress::Id(id) => Ok(Some(id)),
+				_ => Ok(None),
+			}
+		}
+
+		fn validate_transaction(&self, _at: &BlockId, _tx: NodeExtrinsic) -> ApiResult<TransactionValidity> {
+			Ok(TransactionValidity::Unknown)
+		}
+
+		fn evaluate_block(&self, _at: &BlockId, _block: Block) -> ApiResult<bool> {
+			Ok(true)
+		}
+
+		fn build_block(&self, _at: &BlockId, _inherent_data: InherentData) -> ApiResult<TestBlockBuilder> {
+			Ok(TestBlockBuilder)
+		}
+
+		fn would_apply(&self, _builder: &TestBlockBuilder, _extrinsic: &NodeExtrinsic) -> ApiResult<bool> {
+			Ok(true)
+		}
+
+		fn inherent_extrinsics(&self, _at: &BlockId, _inherent_data: InherentData) -> ApiResult<Vec<NodeExtrinsic>> {
+			Ok(Vec::new())
+		}
+	}
+
+	fn uxt(api: &TestApi, who: Keyring, index: Index, era: Era) -> NodeExtrinsic {
+		let checkpoint = api.block_number_to_hash(era.birth(api.current_height())).unwrap();
+		let function = Call::Timestamp(TimestampCall::set(0));
+		let payload = (index, function, era, checkpoint);
+		let signature = who.sign(&payload.encode()).into();
+		let extrinsic = UncheckedExtrinsic {
+			signature: Some((RawAddress::Id(who.to_raw_public().into()), signature, payload.0, era)),
+			function: payload.1,
+		};
+
+		Decode::decode(&mut extrinsic.encode().as_slice()).unwrap()
+	}
+
+	fn pool(api: &Arc<TestApi>) -> TransactionPool<TestApi> {
+		TransactionPool::new(Default::default(), ChainApi::new(api.clone()))
+	}
+
+	fn pending(pool: &TransactionPool<TestApi>, at: BlockNumber) -> Vec<Index> {
+		pool.cull_and_get_pending(&BlockId::number(at), |p| p.map(|tx| tx.verified.index).collect()).unwrap()
+	}
+
+	#[test]
+	fn transaction_with_ended_era_is_culled() {
+		let api = Arc::new(TestApi::default());
+		*api.height.lock() = 10;
+		let pool = pool(&api);
+
+		// valid for blocks 10 to 13.
+		pool.submit_one(&BlockId::number(10), uxt(&api, Keyring::Alice, 0, Era::mortal(4, 10))).unwrap();
+		assert_eq!(pending(&pool, 13), vec![0]);
+
+		*api.height.lock() = 14;
+		assert_eq!(pending(&pool, 14), Vec::<Index>::new());
+		assert_eq!(pool.light_status().transaction_count, 0);
+	}
+
+	#[test]
+	fn transaction_size_limit_is_shared() {
+		assert_eq!(MAX_TRANSACTION_SIZE, primitives::SizeLimits::default().max_transaction_size);
+	}
+
+	#[test]
+	fn local_context_exposes_genesis_hash() {
+		let api = Arc::new(TestApi::default());
+		*api.height.lock() = 10;
+
+		assert_eq!(LocalContext(&api).genesis_hash().unwrap(), api.block_number_to_hash(0).unwrap());
+	}
+
+	#[test]
+	fn immortal_transaction_is_not_culled() {
+		let api = Arc::new(TestApi::default());
+		let pool = pool(&api);
+
+		pool.submit_one(&BlockId::number(0), uxt(&api, Keyring::Alice, 0, Era::immortal())).unwrap();
+		assert_eq!(pending(&pool, 1_000_000), vec![0]);
+	}
+
+	#[test]
+	fn transaction_exceeding_balance_is_rejected() {
+		let api = Arc::new(TestApi { balance: 10, ..Default::default() });
+		let fees = FeeSchedule { base_fee: 5, byte_fee: 1 };
+		let fee_pool = TransactionPool::new(Default::default(), ChainApi::with_fees(api.clone(), fees));
+
+		let err = fee_pool.submit_one(&BlockId::number(0), uxt(&api, Keyring::Alice, 0, Era::immortal())).unwrap_err();
+		match *err.kind() {
+			ErrorKind::InsufficientBalance(fee, 10) => assert!(fee > 10),
+			ref e => panic!("Unexpected error: {:?}", e),
+		}
+
+		// fee checks are off by default.
+		pool(&api).submit_one(&BlockId::number(0), uxt(&api, Keyring::Alice, 0, Era::immortal())).unwrap();
+	}
+
+	#[test]
+	fn transaction_below_minimum_fee_is_rejected() {
+		let api = Arc::new(TestApi { balance: 100, ..Default::default() });
+		let fees = FeeSchedule { base_fee: 5, byte_fee: 0 };
+		let submit = |min_fee| {
+			let pool = TransactionPool::new(Default::default(), ChainApi::with_fees(api.clone(), fees).min_fee(min_fee));
+			pool.submit_one(&BlockId::number(0), uxt(&api, Keyring::Alice, 0, Era::immortal()))
+		};
+
+		match *submit(6).unwrap_err().kind() {
+			ErrorKind::FeeTooLow(5, 6) => {}
+			ref e => panic!("Unexpected error: {:?}", e),
+		}
+		submit(5).unwrap();
+	}
+
+	#[test]
+	fn transaction_valid_beyond_horizon_is_rejected() {
+		let api = Arc::new(TestApi::default());
+		let pool = TransactionPool::new(Default::default(), ChainApi::new(api.clone()).mortality_horizon(256));
+
+		match *pool.submit_one(&BlockId::number(0), uxt(&api, Keyring::Alice, 0, Era::mortal(1024, 0))).unwrap_err().kind() {
+			ErrorKind::MortalityTooLong(1024, 256) => {}
+			ref e => panic!("Unexpected error: {:?}", e),
+		}
+		pool.submit_one(&BlockId::number(0), uxt(&api, Keyring::Alice, 0, Era::mortal(64, 0))).unwrap();
+	}
+
+	#[test]
+	fn transaction_from_new_account_is_accepted_by_default() {
+		let api = Arc::new(TestApi::default());
+		let pool = pool(&api);
+
+		pool.submit_one(&BlockId::number(0), uxt(&api, Keyring::Alice, 0, Era::immortal())).unwrap();
+		assert_eq!(pending(&pool, 0), vec![0]);
+	}
+
+	#[test]
+	fn transaction_from_new_account_is_rejected_unless_allowed() {
+		let api = Arc::new(TestApi::default());
+		let pool = TransactionPool::new(Default::default(), ChainApi::new(api.clone()).allow_new_accounts(false));
+		let alice: AccountId = Keyring::Alice.to_raw_public().into();
+
+		match *pool.submit_one(&BlockId::number(0), uxt(&api, Keyring::Alice, 0, Era::immortal())).unwrap_err().kind() {
+			ErrorKind::UnknownAccount(who) => assert_eq!(who, alice),
+			ref e => panic!("Unexpected error: {:?}", e),
+		}
+
+		let funded = Arc::new(TestApi { balance: 1, ..Default::default() });
+		let pool = TransactionPool::new(Default::default(), ChainApi::new(funded.clone()).allow_new_accounts(false));
+		pool.submit_one(&BlockId::number(0), uxt(&funded, Keyring::Alice, 0, Era::immortal())).unwrap();
+	}
+
+	#[test]
+	fn replacement_policy_chooses_between_same_index_transactions() {
+		let api = Arc::new(TestApi { balance: 1_000, ..Default::default() });
+		let fees = FeeSchedule { base_fee: 0, byte_fee: 1 };
+		// the mortal one is encoded larger, so pays the higher fee.
+		let first = uxt(&api, Keyring::Alice, 0, Era::immortal());
+		let second = uxt(&api, Keyring::Alice, 0, Era::mortal(64, 0));
+		let hash = |xt: &NodeExtrinsic| BlakeTwo256::hash(&xt.encode());
+		let kept = |policy, first: &NodeExtrinsic, second: &NodeExtrinsic| {
+			let pool = TransactionPool::new(Default::default(), ChainApi::with_fees(api.clone(), fees).replacement_policy(policy));
+			pool.submit_one(&BlockId::number(0), first.clone()).unwrap();
+			let _ = pool.submit_one(&BlockId::number(0), second.clone());
+			pool.cull_and_get_pending(&BlockId::number(0), |p| p.map(|tx| tx.verified.hash).collect::<Vec<_>>()).unwrap()
+		};
+
+		assert_eq!(kept(ReplacementPolicy::PreferNew, &first, &second), vec![hash(&second)]);
+		assert_eq!(kept(ReplacementPolicy::PreferOld, &first, &second), vec![hash(&first)]);
+		assert_eq!(kept(ReplacementPolicy::PreferHigherFee, &first, &second), vec![hash(&second)]);
+		assert_eq!(kept(ReplacementPolicy::PreferHigherFee, &second, &first), vec![hash(&second)]);
+	}
+
+	#[test]
+	fn addresses_are_looked_up_at_best_block() {
+		let api = Arc::new(TestApi::default());
+		*api.height.lock() = 5;
+
+		pool(&api).submit_one(&BlockId::number(5), uxt(&api, Keyring::Alice, 0, Era::immortal())).unwrap();
+
+		assert_eq!(*api.lookups.lock(), vec![api.best_block_id().unwrap()]);
+	}
+
+	#[test]
+	fn batch_verification_keeps_input_order() {
+		let api = Arc::new(TestApi::default());
+		let chain_api = ChainApi::new(api.clone()).verification_threads(3);
+		let mut xts: Vec<_> = (0..5).map(|i| uxt(&api, Keyring::Alice, i, Era::immortal())).collect();
+		let inherent = UncheckedExtrinsic::new_unsigned(Call::Timestamp(TimestampCall::set(0)));
+		xts[2] = Decode::decode(&mut inherent.encode().as_slice()).unwrap();
+
+		let results = chain_api.verify_many(&BlockId::number(0), xts.clone());
+
+		assert_eq!(results.len(), xts.len());
+		for (i, (xt, result)) in xts.iter().zip(results).enumerate() {
+			match result {
+				Ok(verified) => {
+					assert_ne!(i, 2);
+					assert_eq!(verified.hash, BlakeTwo256::hash(&xt.encode()));
+					assert_eq!(verified.index, i as Index);
+				}
+				Err(_) => assert_eq!(i, 2),
+			}
+		}
+	}
+
+	#[test]
+	fn panicking_batch_verification_fails_its_batch() {
+		let api = Arc::new(TestApi {
+			balance: 100,
+			panicking: Some(Keyring::Bob.to_raw_public().into()),
+			..Default::default()
+		});
+		let fees = FeeSchedule { base_fee: 1, byte_fee: 0 };
+		let chain_api = ChainApi::with_fees(api.clone(), fees).verification_pool(VerificationPool::new(2));
+		let xts = vec![
+			uxt(&api, Keyring::Alice, 0, Era::immortal()),
+			uxt(&api, Keyring::Alice, 1, Era::immortal()),
+			uxt(&api, Keyring::Bob, 0, Era::immortal()),
+			uxt(&api, Keyring::Bob, 1, Era::immortal()),
+		];
+
+		let results = chain_api.verify_many(&BlockId::number(0), xts);
+
+		assert!(results[0].is_ok() && results[1].is_ok());
+		for result in &results[2..] {
+			match *result.as_ref().unwrap_err().kind() {
+				ErrorKind::VerificationAborted => {}
+				ref e => panic!("Unexpected error: {:?}", e),
+			}
+		}
+
+		// the pool's threads survive the panic.
+		let results = chain_api.verify_many(&BlockId::number(0), vec![
+			uxt(&api, Keyring::Alice, 2, Era::immortal()),
+			uxt(&api, Keyring::Alice, 3, Era::immortal()),
+		]);
+		assert!(results.iter().all(Result::is_ok));
+	}
+
+	#[test]
+	fn resubmitted_transaction_is_checked_once() {
+		let api = Arc::new(TestApi::default());
+		let pool = pool(&api);
+		let xt = uxt(&api, Keyring::Alice, 0, Era::immortal());
+
+		let hash = pool.submit_one(&BlockId::number(0), xt.clone()).unwrap().verified.hash;
+		pool.remove(&[hash], true);
+		pool.submit_one(&BlockId::number(0), xt).unwrap();
+
+		assert_eq!(api.lookups.lock().len(), 1);
+	}
+
+	#[test]
+	fn submission_to_full_pool_is_rejected_as_full() {
+		let api = Arc::new(TestApi::default());
+		let options = Options { max_count: 1, ..Default::default() };
+		let pool = TransactionPool::new(options, ChainApi::new(api.clone()));
+
+		pool.submit_one(&BlockId::number(0), uxt(&api, Keyring::Alice, 0, Era::immortal())).unwrap();
+		let err = pool.submit_one(&BlockId::number(0), uxt(&api, Keyring::Bob, 0, Era::immortal())).unwrap_err();
+		match *err.kind() {
+			ErrorKind::PoolFull(_) => {},
+			ref e => panic!("Unexpected error: {:?}", e),
+		}
+	}
+
+	#[test]
+	fn equal_indices_of_different_senders_are_ordered() {
+		let api = Arc::new(TestApi::default());
+		let pool = pool(&api);
+		let imported = pool.submit_at(&BlockId::number(0), vec![
+			uxt(&api, Keyring::Alice, 0, Era::immortal()),
+			uxt(&api, Keyring::Bob, 0, Era::immortal()),
+		]).unwrap();
+
+		let compare = <ChainApi<TestApi> as transaction_pool::ChainApi>::compare;
+		assert_ne!(compare(&imported[0], &imported[1]), Ordering::Equal);
+		assert_eq!(compare(&imported[0], &imported[1]), compare(&imported[1], &imported[0]).reverse());
+	}
+
+	#[test]
+	fn preseeded_readiness_needs_no_index_lookups() {
+		use transaction_pool::ChainApi as ChainApiT;
+
+		let api = Arc::new(TestApi::default());
+		let xt = pool(&api).submit_one(&BlockId::number(0), uxt(&api, Keyring::Alice, 0, Era::immortal())).unwrap();
+		let chain_api = ChainApi::new(api.clone());
+
+		let mut ready = chain_api.ready_at(&BlockId::number(0), &[xt.verified.sender]);
+		let calls = *api.index_calls.lock();
+
+		assert_eq!(chain_api.is_ready(&BlockId::number(0), &mut ready, &xt), Readiness::Ready);
+		assert_eq!(*api.index_calls.lock(), calls);
+	}
+
+	#[test]
+	fn invalidated_sender_index_is_fetched_again() {
+		use transaction_pool::ChainApi as ChainApiT;
+
+		let api = Arc::new(TestApi::default());
+		let pool = TransactionPool::new(Default::default(), ChainApi::new(api.clone()).with_nonce_cache());
+		let xt = pool.submit_one(&BlockId::number(0), uxt(&api, Keyring::Alice, 0, Era::immortal())).unwrap();
+		let is_ready = |at| pool.api().is_ready(&BlockId::number(at), &mut pool.api().ready(), &xt);
+
+		let calls = *api.index_calls.lock();
+		assert_eq!(is_ready(0), Readiness::Ready);
+		assert_eq!(is_ready(0), Readiness::Ready);
+		assert_eq!(*api.index_calls.lock(), calls + 1);
+
+		pool.api().invalidate_sender(&xt.verified.sender);
+		assert_eq!(is_ready(0), Readiness::Ready);
+		assert_eq!(*api.index_calls.lock(), calls + 2);
+
+		// indices cached at another block aren't used.
+		assert_eq!(is_ready(1), Readiness::Ready);
+		assert_eq!(*api.index_calls.lock(), calls + 3);
+		assert_eq!(pool.api().cached_nonce(&BlockId::number(0), &xt.verified.sender), None);
+	}
+
+	thread_local! {
+		static LOGGED: ::std::cell::RefCell<Vec<String>> = Default::default();
+	}
+
+	// Keeps the messages logged on each thread apart, so tests can run in parallel.
+	struct ThreadLogger;
+
+	impl ::log::Log for ThreadLogger {
+		fn enabled(&self, _metadata: &::log::LogMetadata) -> bool {
+			true
+		}
+
+		fn log(&self, record: &::log::LogRecord) {
+			LOGGED.with(|logged| logged.borrow_mut().push(record.args().to_string()));
+		}
+	}
+
+	#[test]
+	fn payloads_are_not_logged_when_disabled() {
+		static INIT: ::std::sync::Once = ::std::sync::ONCE_INIT;
+		INIT.call_once(|| {
+			::log::set_logger(|max_level| {
+				max_level.set(::log::LogLevelFilter::Trace);
+				Box::new(ThreadLogger)
+			}).expect("no other logger is set in tests; qed");
+		});
+
+		let api = Arc::new(TestApi::default());
+		let pool = TransactionPool::new(Default::default(), ChainApi::new(api.clone()).log_payloads(false));
+		let xt = uxt(&api, Keyring::Alice, 0, Era::immortal());
+		let payload = format!("{}", ::substrate_primitives::hexdisplay::HexDisplay::from(&xt.encode()));
+
+		let hash = pool.submit_one(&BlockId::number(0), xt).unwrap().verified.hash;
+
+		LOGGED.with(|logged| {
+			let logged = logged.borrow();
+			assert!(logged.iter().any(|line| line.contains(&format!("{}", hash))));
+			assert!(!logged.iter().any(|line| line.contains(&payload)));
+		});
+	}
+}